@@ -3,6 +3,7 @@
 
 # Define variables
 binary_name="makima"
+client_name="makimactl"
 service_name="makima.service"
 rules_name="50-makima.rules"
 
@@ -30,6 +31,7 @@ undo_changes() {
     systemctl stop $service_name
     systemctl disable $service_name
     rm /usr/local/bin/$binary_name
+    rm -f /usr/local/bin/$client_name
     rm /etc/udev/rules.d/$rules_name
     rm /etc/systemd/system/$service_name
     rm /etc/modules-load.d/uinput.conf
@@ -56,6 +58,10 @@ fi
 # Copy the binary, udev rules and create the configuration folder
 chmod +x "$binary_name"
 cp "$binary_name" /usr/local/bin/
+if [ -f "$client_name" ]; then
+    chmod +x "$client_name"
+    cp "$client_name" /usr/local/bin/
+fi
 cp "$rules_name" /etc/udev/rules.d/
 echo "uinput" > /etc/modules-load.d/uinput.conf
 mkdir /home/"$user_name"/.config/makima
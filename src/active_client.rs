@@ -1,179 +1,376 @@
 use crate::udev_monitor::{Client, Environment, Server};
 use crate::Config;
 use serde_json;
-use std::process::{Command, Stdio};
-use swayipc_async::Connection;
-use x11rb::protocol::xproto::{get_input_focus, get_property, Atom, AtomEnum};
+use std::{collections::HashMap, env, future::Future, process::Stdio, sync::Arc};
+use swayipc_async::{Connection, EventType, Node, WindowChange};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::net::UnixStream;
+use tokio::process::Command;
+use tokio::sync::Mutex;
+use tokio::time::{Duration, Instant};
+use tokio_stream::StreamExt;
+use x11rb::connection::Connection as X11Connection;
+use x11rb::protocol::xproto::{
+    change_window_attributes, get_property, intern_atom, Atom, AtomEnum, ChangeWindowAttributesAux,
+    EventMask, Window,
+};
 
-pub async fn get_active_window(environment: &Environment, config: &Vec<Config>) -> Client {
-    match &environment.server {
-        Server::Connected(server) => {
-            let server_str = server.as_str();
-            match server_str {
-                "Hyprland" => {
-                    let query = Command::new("hyprctl")
-                        .args(["activewindow", "-j"])
-                        .output()
-                        .unwrap();
-                    if let Ok(reply) = serde_json::from_str::<serde_json::Value>(
-                        std::str::from_utf8(query.stdout.as_slice()).unwrap(),
-                    ) {
-                        let active_window =
-                            Client::Class(reply["class"].to_string().replace("\"", ""));
-                        if let Some(_) = config
-                            .iter()
-                            .find(|&x| x.associations.client == active_window)
-                        {
-                            active_window
-                        } else {
-                            Client::Default
-                        }
-                    } else {
-                        Client::Default
-                    }
-                }
-                "sway" => {
-                    let mut connection = Connection::new().await.unwrap();
-                    let active_window = match connection
-                        .get_tree()
+const RECONNECT_MIN_DELAY: Duration = Duration::from_secs(1);
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(30);
+// KWin has no socket to subscribe to, so kdotool is polled instead of run on every event.
+const KDE_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+pub fn start_window_watcher(environment: &Environment) -> Arc<Mutex<Client>> {
+    let active_window: Arc<Mutex<Client>> = Arc::new(Mutex::new(Client::Default));
+    if let Server::Connected(server) = &environment.server {
+        match server.as_str() {
+            "Hyprland" => {
+                let Ok(signature) = env::var("HYPRLAND_INSTANCE_SIGNATURE") else {
+                    println!("Warning: HYPRLAND_INSTANCE_SIGNATURE is not set, unable to track the active window.\n");
+                    return active_window;
+                };
+                let watch = move |active_window| watch_hyprland(active_window, signature.clone());
+                tokio::spawn(keep_watching("Hyprland", active_window.clone(), watch));
+            }
+            "sway" => {
+                tokio::spawn(keep_watching("sway", active_window.clone(), watch_sway));
+            }
+            "niri" => {
+                tokio::spawn(keep_watching("niri", active_window.clone(), watch_niri));
+            }
+            "KDE" => {
+                let environment = environment.clone();
+                let watch = move |active_window| watch_kde(active_window, environment.clone());
+                tokio::spawn(keep_watching("KDE", active_window.clone(), watch));
+            }
+            "x11" => {
+                let watch = |active_window| async {
+                    tokio::task::spawn_blocking(move || watch_x11(active_window))
                         .await
-                        .unwrap()
-                        .find_focused(|window| window.focused)
-                    {
-                        Some(window) => match window.app_id {
-                            Some(id) => Client::Class(id),
-                            None => window
-                                .window_properties
-                                .and_then(|window_properties| window_properties.class)
-                                .map_or(Client::Default, Client::Class),
-                        },
-                        None => Client::Default,
-                    };
-                    if let Some(_) = config
-                        .iter()
-                        .find(|&x| x.associations.client == active_window)
-                    {
-                        active_window
-                    } else {
-                        Client::Default
-                    }
-                }
-                "niri" => {
-                    let query = Command::new("niri")
-                        .args(["msg", "-j", "focused-window"])
-                        .output()
-                        .unwrap();
-                    if let Ok(reply) = serde_json::from_str::<serde_json::Value>(
-                        std::str::from_utf8(query.stdout.as_slice()).unwrap(),
-                    ) {
-                        let active_window =
-                            Client::Class(reply["app_id"].to_string().replace("\"", ""));
-                        if let Some(_) = config
-                            .iter()
-                            .find(|&x| x.associations.client == active_window)
-                        {
-                            active_window
-                        } else {
-                            Client::Default
-                        }
-                    } else {
-                        Client::Default
-                    }
-                }
-                "KDE" => {
-                    let (user, running_as_root) =
-                        if let Ok(sudo_user) = environment.sudo_user.clone() {
-                            (Option::Some(sudo_user), true)
-                        } else if let Ok(user) = environment.user.clone() {
-                            (Option::Some(user), false)
-                        } else {
-                            (Option::None, false)
-                        };
-                    let active_window = {
-                        if let Some(user) = user {
-                            if running_as_root {
-                                let output = Command::new("runuser")
-                                    .arg(user)
-                                    .arg("-c")
-                                    .arg("kdotool getactivewindow getwindowclassname")
-                                    .output()
-                                    .unwrap();
-                                Client::Class(
-                                    std::str::from_utf8(output.stdout.as_slice())
-                                        .unwrap()
-                                        .trim()
-                                        .to_string(),
-                                )
-                            } else {
-                                let output = Command::new("sh")
-                                    .arg("-c")
-                                    .arg(format!("systemd-run --user --scope -M {}@ kdotool getactivewindow getwindowclassname", user))
-                                    .stderr(Stdio::null())
-                                    .output()
-                                    .unwrap();
-                                Client::Class(
-                                    std::str::from_utf8(output.stdout.as_slice())
-                                        .unwrap()
-                                        .trim()
-                                        .to_string(),
-                                )
-                            }
-                        } else {
-                            Client::Default
-                        }
-                    };
-                    if let Some(_) = config
-                        .iter()
-                        .find(|&x| x.associations.client == active_window)
-                    {
-                        active_window
-                    } else {
-                        Client::Default
+                        .map_err(|error| error.to_string())?
+                };
+                tokio::spawn(keep_watching("the X server", active_window.clone(), watch));
+            }
+            _ => {}
+        }
+    }
+    active_window
+}
+
+async fn keep_watching<F, Fut>(server: &str, active_window: Arc<Mutex<Client>>, watch: F)
+where
+    F: Fn(Arc<Mutex<Client>>) -> Fut,
+    Fut: Future<Output = Result<(), String>>,
+{
+    let mut backoff = RECONNECT_MIN_DELAY;
+    loop {
+        let connected_at = Instant::now();
+        match watch(active_window.clone()).await {
+            Ok(()) => println!(
+                "Lost connection to {}, reconnecting in {}s.\n",
+                server,
+                backoff.as_secs()
+            ),
+            Err(error) => println!(
+                "Warning: {}, unable to track the active window on {}. Retrying in {}s.\n",
+                error,
+                server,
+                backoff.as_secs()
+            ),
+        }
+        *active_window.lock().await = Client::Default;
+        tokio::time::sleep(backoff).await;
+        backoff = if connected_at.elapsed() > RECONNECT_MAX_DELAY * 2 {
+            RECONNECT_MIN_DELAY
+        } else {
+            (backoff * 2).min(RECONNECT_MAX_DELAY)
+        };
+    }
+}
+
+pub async fn get_active_window(
+    environment: &Environment,
+    config: &Vec<Config>,
+    active_window: &Arc<Mutex<Client>>,
+) -> Client {
+    filter_active_window(
+        config,
+        get_raw_active_window(environment, active_window).await,
+    )
+}
+
+pub async fn get_raw_active_window(
+    environment: &Environment,
+    active_window: &Arc<Mutex<Client>>,
+) -> Client {
+    match &environment.server {
+        Server::Connected(_) => active_window.lock().await.clone(),
+        Server::Unsupported => Client::Default,
+        Server::Failed => Client::Default,
+    }
+}
+
+pub fn filter_active_window(config: &Vec<Config>, active_window: Client) -> Client {
+    if let Some(_) = config
+        .iter()
+        .find(|&x| x.associations.client == active_window)
+    {
+        active_window
+    } else {
+        Client::Default
+    }
+}
+
+async fn watch_hyprland(
+    active_window: Arc<Mutex<Client>>,
+    signature: String,
+) -> Result<(), String> {
+    let mut socket_paths = vec![format!("/tmp/hypr/{}/.socket2.sock", signature)];
+    if let Ok(runtime_dir) = env::var("XDG_RUNTIME_DIR") {
+        socket_paths.insert(
+            0,
+            format!("{}/hypr/{}/.socket2.sock", runtime_dir, signature),
+        );
+    }
+    let mut socket = None;
+    for path in socket_paths {
+        if let Ok(stream) = UnixStream::connect(path).await {
+            socket = Some(stream);
+            break;
+        }
+    }
+    let Some(socket) = socket else {
+        return Err("unable to connect to Hyprland's event socket".to_string());
+    };
+    if let Ok(query) = Command::new("hyprctl")
+        .args(["activewindow", "-j"])
+        .stderr(Stdio::null())
+        .output()
+        .await
+    {
+        if let Ok(reply) = serde_json::from_slice::<serde_json::Value>(&query.stdout) {
+            *active_window.lock().await = match reply["class"].as_str() {
+                Some(class) if !class.is_empty() => Client::Class(class.to_string()),
+                _ => Client::Default,
+            };
+        }
+    }
+    let mut lines = BufReader::new(socket).lines();
+    while let Some(line) = lines.next_line().await.map_err(|error| error.to_string())? {
+        if let Some(data) = line.strip_prefix("activewindow>>") {
+            let class = data.split_once(",").map_or(data, |(class, _title)| class);
+            *active_window.lock().await = if class.is_empty() {
+                Client::Default
+            } else {
+                Client::Class(class.to_string())
+            };
+        }
+    }
+    Ok(())
+}
+
+async fn watch_sway(active_window: Arc<Mutex<Client>>) -> Result<(), String> {
+    let Ok(mut connection) = Connection::new().await else {
+        return Err("unable to connect to sway's IPC socket".to_string());
+    };
+    if let Ok(tree) = connection.get_tree().await {
+        *active_window.lock().await = match tree.find_focused(|window| window.focused) {
+            Some(window) => get_sway_class(window),
+            None => Client::Default,
+        };
+    }
+    let Ok(mut events) = connection.subscribe([EventType::Window]).await else {
+        return Err("unable to subscribe to sway's window events".to_string());
+    };
+    while let Some(event) = events.next().await {
+        if let swayipc_async::Event::Window(window_event) =
+            event.map_err(|error| error.to_string())?
+        {
+            if let WindowChange::Focus = window_event.change {
+                *active_window.lock().await = get_sway_class(window_event.container);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn get_sway_class(window: Node) -> Client {
+    match window.app_id {
+        Some(id) => Client::Class(id),
+        None => window
+            .window_properties
+            .and_then(|window_properties| window_properties.class)
+            .map_or(Client::Default, Client::Class),
+    }
+}
+
+async fn watch_niri(active_window: Arc<Mutex<Client>>) -> Result<(), String> {
+    let Ok(mut child) = Command::new("niri")
+        .args(["msg", "-j", "event-stream"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+    else {
+        return Err("unable to read niri's event stream".to_string());
+    };
+    let Some(stdout) = child.stdout.take() else {
+        return Err("unable to read niri's event stream".to_string());
+    };
+    let mut lines = BufReader::new(stdout).lines();
+    let mut windows: HashMap<u64, String> = HashMap::new();
+    let mut focused_window: Option<u64> = None;
+    while let Some(line) = lines.next_line().await.map_err(|error| error.to_string())? {
+        let Ok(event) = serde_json::from_str::<serde_json::Value>(&line) else {
+            continue;
+        };
+        if let Some(window_list) = event["WindowsChanged"]["windows"].as_array() {
+            windows.clear();
+            focused_window = None;
+            for window in window_list {
+                if let Some(id) = window["id"].as_u64() {
+                    windows.insert(id, window["app_id"].as_str().unwrap_or("").to_string());
+                    if window["is_focused"].as_bool() == Some(true) {
+                        focused_window = Some(id);
                     }
                 }
-                "x11" => {
-                    let connection = x11rb::connect(None).unwrap().0;
-                    let focused_window =
-                        get_input_focus(&connection).unwrap().reply().unwrap().focus;
-                    let (wm_class, string): (Atom, Atom) =
-                        (AtomEnum::WM_CLASS.into(), AtomEnum::STRING.into());
-                    let class = get_property(
-                        &connection,
-                        false,
-                        focused_window,
-                        wm_class,
-                        string,
-                        0,
-                        u32::MAX,
-                    )
-                    .unwrap()
-                    .reply()
-                    .unwrap()
-                    .value;
-                    if let Some(middle) = class.iter().position(|&byte| byte == 0) {
-                        let class = class.split_at(middle).1;
-                        let mut class = &class[1..];
-                        if class.last() == Some(&0) {
-                            class = &class[..class.len() - 1];
-                        }
-                        let active_window =
-                            Client::Class(std::str::from_utf8(class).unwrap().to_string());
-                        if let Some(_) = config
-                            .iter()
-                            .find(|&x| x.associations.client == active_window)
-                        {
-                            active_window
-                        } else {
-                            Client::Default
-                        }
-                    } else {
-                        Client::Default
-                    }
+            }
+        } else if event["WindowOpenedOrChanged"].is_object() {
+            let window = &event["WindowOpenedOrChanged"]["window"];
+            if let Some(id) = window["id"].as_u64() {
+                windows.insert(id, window["app_id"].as_str().unwrap_or("").to_string());
+                if window["is_focused"].as_bool() == Some(true) {
+                    focused_window = Some(id);
                 }
-                _ => Client::Default,
             }
+        } else if let Some(id) = event["WindowClosed"]["id"].as_u64() {
+            windows.remove(&id);
+            if focused_window == Some(id) {
+                focused_window = None;
+            }
+        } else if event["WindowFocusChanged"].is_object() {
+            focused_window = event["WindowFocusChanged"]["id"].as_u64();
+        } else {
+            continue;
         }
-        Server::Unsupported => Client::Default,
-        Server::Failed => Client::Default,
+        *active_window.lock().await = match focused_window.and_then(|id| windows.get(&id)) {
+            Some(app_id) if !app_id.is_empty() => Client::Class(app_id.clone()),
+            _ => Client::Default,
+        };
+    }
+    Ok(())
+}
+
+fn watch_x11(active_window: Arc<Mutex<Client>>) -> Result<(), String> {
+    let (connection, screen) = x11rb::connect(None).map_err(|error| error.to_string())?;
+    let root = connection.setup().roots[screen].root;
+    let net_active_window: Atom = intern_atom(&connection, false, b"_NET_ACTIVE_WINDOW")
+        .map_err(|error| error.to_string())?
+        .reply()
+        .map_err(|error| error.to_string())?
+        .atom;
+    change_window_attributes(
+        &connection,
+        root,
+        &ChangeWindowAttributesAux::new().event_mask(EventMask::PROPERTY_CHANGE),
+    )
+    .map_err(|error| error.to_string())?;
+    connection.flush().map_err(|error| error.to_string())?;
+    *active_window.blocking_lock() = get_x11_class(&connection, root, net_active_window);
+    loop {
+        let event = connection
+            .wait_for_event()
+            .map_err(|error| error.to_string())?;
+        if let x11rb::protocol::Event::PropertyNotify(event) = event {
+            if event.window == root && event.atom == net_active_window {
+                *active_window.blocking_lock() =
+                    get_x11_class(&connection, root, net_active_window);
+            }
+        }
+    }
+}
+
+fn get_x11_class(connection: &impl X11Connection, root: Window, net_active_window: Atom) -> Client {
+    let focused_window = match get_property(
+        connection,
+        false,
+        root,
+        net_active_window,
+        AtomEnum::WINDOW,
+        0,
+        1,
+    )
+    .ok()
+    .and_then(|cookie| cookie.reply().ok())
+    .and_then(|reply| reply.value32().and_then(|mut value| value.next()))
+    {
+        Some(window) if window != 0 => window,
+        _ => return Client::Default,
+    };
+    let (wm_class, string): (Atom, Atom) = (AtomEnum::WM_CLASS.into(), AtomEnum::STRING.into());
+    let Some(class) = get_property(
+        connection,
+        false,
+        focused_window,
+        wm_class,
+        string,
+        0,
+        u32::MAX,
+    )
+    .ok()
+    .and_then(|cookie| cookie.reply().ok())
+    .map(|reply| reply.value) else {
+        return Client::Default;
+    };
+    if let Some(middle) = class.iter().position(|&byte| byte == 0) {
+        let class = class.split_at(middle).1;
+        let mut class = &class[1..];
+        if class.last() == Some(&0) {
+            class = &class[..class.len() - 1];
+        }
+        Client::Class(std::str::from_utf8(class).unwrap_or_default().to_string())
+    } else {
+        Client::Default
+    }
+}
+
+async fn watch_kde(
+    active_window: Arc<Mutex<Client>>,
+    environment: Environment,
+) -> Result<(), String> {
+    loop {
+        let client = get_kde_active_window(&environment).await?;
+        *active_window.lock().await = client;
+        tokio::time::sleep(KDE_POLL_INTERVAL).await;
+    }
+}
+
+async fn get_kde_active_window(environment: &Environment) -> Result<Client, String> {
+    let command = if let Ok(sudo_user) = &environment.sudo_user {
+        Command::new("runuser")
+            .arg(sudo_user)
+            .arg("-c")
+            .arg("kdotool getactivewindow getwindowclassname")
+            .output()
+            .await
+    } else if let Ok(user) = &environment.user {
+        Command::new("sh")
+            .arg("-c")
+            .arg(format!(
+                "systemd-run --user --scope -M {}@ kdotool getactivewindow getwindowclassname",
+                user
+            ))
+            .stderr(Stdio::null())
+            .output()
+            .await
+    } else {
+        return Err("no user to run kdotool as".to_string());
+    };
+    match command {
+        Ok(output) => match String::from_utf8_lossy(&output.stdout).trim() {
+            "" => Ok(Client::Default),
+            class => Ok(Client::Class(class.to_string())),
+        },
+        Err(error) => Err(format!("unable to run kdotool: {}", error)),
     }
 }
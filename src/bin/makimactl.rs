@@ -0,0 +1,48 @@
+#[path = "../socket.rs"]
+mod socket;
+
+use socket::get_socket_path;
+use std::{
+    env,
+    io::{Read, Write},
+    os::unix::net::UnixStream,
+};
+
+const USAGE: &str = "Usage: makimactl <command>
+
+Commands:
+  list                       List the devices being read, their config files and state
+  layout <number> [device]   Switch to a layout, on all devices or only on the given one
+  reload                     Reload all config files
+  pause [device]             Stop remapping and pass the original events through
+  resume [device]            Resume remapping
+
+Devices can be given as their event device (e.g. \"event5\") or as their name.";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.is_empty() || args[0] == "-h" || args[0] == "--help" {
+        println!("{}", USAGE);
+        return;
+    }
+    let socket_path = get_socket_path();
+    let mut stream = match UnixStream::connect(&socket_path) {
+        Ok(stream) => stream,
+        Err(error) => {
+            eprintln!(
+                "Unable to connect to {:?}: {}, is Makima running?",
+                socket_path, error
+            );
+            std::process::exit(1);
+        }
+    };
+    stream
+        .write_all(format!("{}\n", args.join(" ")).as_bytes())
+        .unwrap();
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply).unwrap();
+    print!("{}", reply);
+    if reply.contains("Error: ") {
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,501 @@
+use crate::config::{
+    is_config_file, parse_associations, parse_binding, parse_chord, parse_chord_remap,
+    parse_commands, parse_movement, parse_remap, parse_sequence, validate_setting, DeviceMatch,
+    Event,
+};
+use crate::udev_monitor::Client;
+use serde::de::{Deserialize, Deserializer, MapAccess, Visitor};
+use std::{collections::HashMap, fmt, ops::Range};
+use toml::Spanned;
+
+enum Section {
+    Table(Vec<(Spanned<String>, Spanned<toml::Value>)>),
+    Value,
+}
+
+impl<'de> Deserialize<'de> for Section {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct SectionVisitor;
+
+        impl<'de> Visitor<'de> for SectionVisitor {
+            type Value = Section;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a section or a value")
+            }
+
+            fn visit_map<M: MapAccess<'de>>(self, mut map: M) -> Result<Section, M::Error> {
+                let mut entries = Vec::new();
+                while let Some(entry) = map.next_entry()? {
+                    entries.push(entry);
+                }
+                Ok(Section::Table(entries))
+            }
+
+            fn visit_seq<S: serde::de::SeqAccess<'de>>(
+                self,
+                mut seq: S,
+            ) -> Result<Section, S::Error> {
+                while seq.next_element::<toml::Value>()?.is_some() {}
+                Ok(Section::Value)
+            }
+
+            fn visit_bool<E>(self, _: bool) -> Result<Section, E> {
+                Ok(Section::Value)
+            }
+
+            fn visit_i64<E>(self, _: i64) -> Result<Section, E> {
+                Ok(Section::Value)
+            }
+
+            fn visit_f64<E>(self, _: f64) -> Result<Section, E> {
+                Ok(Section::Value)
+            }
+
+            fn visit_str<E>(self, _: &str) -> Result<Section, E> {
+                Ok(Section::Value)
+            }
+        }
+
+        deserializer.deserialize_any(SectionVisitor)
+    }
+}
+
+struct Problem {
+    span: Option<Range<usize>>,
+    message: String,
+}
+
+pub fn check_config_dir(config_path: &str) -> i32 {
+    let config_dir = match std::fs::read_dir(config_path) {
+        Ok(dir) => dir,
+        _ => {
+            println!("Directory not found.");
+            return 1;
+        }
+    };
+    let mut filenames: Vec<String> = config_dir
+        .filter_map(|file| file.ok()?.file_name().into_string().ok())
+        .filter(|filename| is_config_file(filename))
+        .collect();
+    filenames.sort();
+    let contents: Vec<std::io::Result<String>> = filenames
+        .iter()
+        .map(|filename| {
+            std::fs::read_to_string(format!(
+                "{}/{}",
+                config_path.trim_end_matches("/"),
+                filename
+            ))
+        })
+        .collect();
+    let layout_names: Vec<(String, Option<String>)> = filenames
+        .iter()
+        .zip(&contents)
+        .map(|(filename, content)| {
+            let layout_name = content
+                .as_ref()
+                .ok()
+                .and_then(|content| toml::from_str::<toml::Table>(content).ok())
+                .and_then(|table| {
+                    Some(
+                        table
+                            .get("settings")?
+                            .get("LAYOUT_NAME")?
+                            .as_str()?
+                            .to_string(),
+                    )
+                });
+            (
+                filename.split(".toml").collect::<Vec<&str>>()[0].to_string(),
+                layout_name,
+            )
+        })
+        .collect();
+    let (mut problem_count, mut files_with_problems) = (0, 0);
+    for ((filename, content), (name, layout_name)) in
+        filenames.iter().zip(&contents).zip(&layout_names)
+    {
+        let problems = match content {
+            Ok(content) => {
+                let mut problems = find_problems(filename, content);
+                problems.extend(find_layout_name_problems(
+                    name,
+                    layout_name.as_deref(),
+                    &layout_names,
+                ));
+                problems
+            }
+            Err(error) => vec![format!("{}: unable to read file: {}", filename, error)],
+        };
+        for problem in &problems {
+            println!("{}", problem);
+        }
+        if !problems.is_empty() {
+            problem_count += problems.len();
+            files_with_problems += 1;
+        }
+    }
+    if problem_count == 0 {
+        println!(
+            "Checked {} config files, no problems found.",
+            filenames.len()
+        );
+        0
+    } else {
+        println!(
+            "\nChecked {} config files, found {} problems in {} of them.",
+            filenames.len(),
+            problem_count,
+            files_with_problems
+        );
+        1
+    }
+}
+
+pub fn find_problems(filename: &str, content: &str) -> Vec<String> {
+    check_config_file(filename, content)
+        .into_iter()
+        .map(|problem| match problem.span {
+            Some(span) => {
+                let (line, column) = get_position(content, span.start);
+                format!("{}:{}:{}: {}", filename, line, column, problem.message)
+            }
+            None => format!("{}: {}", filename, problem.message),
+        })
+        .collect()
+}
+
+pub fn find_layout_name_problems(
+    config_name: &str,
+    layout_name: Option<&str>,
+    config_names: &[(String, Option<String>)],
+) -> Vec<String> {
+    let device = config_name.split("::").collect::<Vec<&str>>()[0];
+    let Ok((client, layout)) = parse_associations(config_name) else {
+        return Vec::new();
+    };
+    let mut problems: Vec<String> = Vec::new();
+    for (other_name, other_layout_name) in config_names {
+        if other_name == config_name || other_name.split("::").collect::<Vec<&str>>()[0] != device {
+            continue;
+        }
+        let (Some(other_layout_name), Ok((_, other_layout))) =
+            (other_layout_name, parse_associations(other_name))
+        else {
+            continue;
+        };
+        if layout_name == Some(other_layout_name.as_str()) && layout != other_layout {
+            problems.push(format!(
+                "{}.toml: layout name {:?} is already used by layout {} in {}.toml",
+                config_name, other_layout_name, other_layout, other_name
+            ));
+        }
+        if client == Client::Class(other_layout_name.clone()) {
+            problems.push(format!(
+                "{}.toml: {:?} in the file name is read as a window class, not as a layout name, use the layout number {} instead",
+                config_name, other_layout_name, other_layout
+            ));
+        }
+    }
+    problems.dedup();
+    problems
+}
+
+fn check_config_file(filename: &str, content: &str) -> Vec<Problem> {
+    let mut problems: Vec<Problem> = Vec::new();
+    let name = filename.split(".toml").collect::<Vec<&str>>()[0];
+    if let Err(error) = parse_associations(name) {
+        problems.push(Problem {
+            span: None,
+            message: error,
+        });
+    } else if name.split("::").any(|association| association.is_empty()) {
+        problems.push(Problem {
+            span: None,
+            message: format!(
+                "empty device name, window class or layout in config file name {}",
+                name
+            ),
+        });
+    }
+    let sections: Vec<(Spanned<String>, Spanned<Section>)> =
+        match toml::from_str::<HashMap<Spanned<String>, Spanned<Section>>>(content) {
+            Ok(sections) => {
+                let mut sections: Vec<(Spanned<String>, Spanned<Section>)> =
+                    sections.into_iter().collect();
+                sections.sort_by_key(|(name, _)| name.span().start);
+                sections
+            }
+            Err(error) => {
+                problems.push(Problem {
+                    span: error.span(),
+                    message: error.message().to_string(),
+                });
+                return problems;
+            }
+        };
+    let mut declared_bindings: HashMap<(Event, Vec<Event>), (&str, String)> = HashMap::new();
+    for (section_name, section) in &sections {
+        let section_span = section_name.span();
+        let Section::Table(entries) = section.get_ref() else {
+            problems.push(Problem {
+                span: Some(section_span),
+                message: format!(
+                    "{:?} is outside of a section, did you mean to put it in [settings]?",
+                    section_name.get_ref()
+                ),
+            });
+            continue;
+        };
+        let section_name = section_name.get_ref().as_str();
+        match section_name {
+            "remap" | "commands" | "movements" => {
+                for (input, output) in entries {
+                    if section_name == "remap" && input.get_ref().contains("+") {
+                        if let Err(error) = parse_chord(input.get_ref()) {
+                            problems.push(Problem {
+                                span: Some(input.span()),
+                                message: format!(
+                                    "{} in [remap] binding {:?}",
+                                    error,
+                                    input.get_ref()
+                                ),
+                            });
+                        }
+                        if let Err(error) = parse_chord_remap(output.get_ref()) {
+                            problems.push(Problem {
+                                span: Some(output.span()),
+                                message: format!(
+                                    "{} in [remap] binding {:?}",
+                                    error,
+                                    input.get_ref()
+                                ),
+                            });
+                        }
+                        continue;
+                    }
+                    let binding = if input.get_ref().contains(" ") {
+                        parse_sequence(input.get_ref()).map(|sequence| (Event::Hold, sequence))
+                    } else {
+                        parse_binding(input.get_ref())
+                    };
+                    if let Err(error) = &binding {
+                        problems.push(Problem {
+                            span: Some(input.span()),
+                            message: format!(
+                                "{} in [{}] binding {:?}",
+                                error,
+                                section_name,
+                                input.get_ref()
+                            ),
+                        });
+                    }
+                    let result = match section_name {
+                        "remap" => parse_remap(output.get_ref()).map(|_| ()),
+                        "commands" => parse_commands(output.get_ref()).map(|_| ()),
+                        _ => parse_movement(output.get_ref()).map(|_| ()),
+                    };
+                    if let Err(error) = result {
+                        problems.push(Problem {
+                            span: Some(output.span()),
+                            message: format!(
+                                "{} in [{}] binding {:?}",
+                                error,
+                                section_name,
+                                input.get_ref()
+                            ),
+                        });
+                    }
+                    let Ok((event, mut modifiers)) = binding else {
+                        continue;
+                    };
+                    if section_name == "commands" {
+                        modifiers.retain(|&modifier| modifier != Event::Hold);
+                    }
+                    if let Some((other_section, other_input)) = declared_bindings
+                        .insert((event, modifiers), (section_name, input.get_ref().clone()))
+                    {
+                        problems.push(Problem {
+                            span: Some(input.span()),
+                            message: format!(
+                                "binding {:?} in [{}] is already declared as {:?} in [{}]",
+                                input.get_ref(),
+                                section_name,
+                                other_input,
+                                other_section
+                            ),
+                        });
+                    }
+                }
+            }
+            "device" => {
+                if entries.is_empty() {
+                    problems.push(Problem {
+                        span: Some(section_span),
+                        message: "the [device] table is empty".to_string(),
+                    });
+                }
+                let mut device_match: DeviceMatch = Default::default();
+                for (field, value) in entries {
+                    if let Err(error) = device_match.set_field(field.get_ref(), value.get_ref()) {
+                        let span = if error.starts_with("unknown field") {
+                            field.span()
+                        } else {
+                            value.span()
+                        };
+                        problems.push(Problem {
+                            span: Some(span),
+                            message: error,
+                        });
+                    }
+                }
+            }
+            "settings" => {
+                for (setting, value) in entries {
+                    let result = match value.get_ref().as_str() {
+                        Some(value) => validate_setting(setting.get_ref(), value),
+                        None => Err(format!(
+                            "value of {} must be a string, e.g. {} = \"{}\"",
+                            setting.get_ref(),
+                            setting.get_ref(),
+                            value.get_ref()
+                        )),
+                    };
+                    if let Err(error) = result {
+                        let span = if error.starts_with("unknown setting") {
+                            setting.span()
+                        } else {
+                            value.span()
+                        };
+                        problems.push(Problem {
+                            span: Some(span),
+                            message: error,
+                        });
+                    }
+                }
+            }
+            _ => problems.push(Problem {
+                span: Some(section_span),
+                message: format!("unknown section [{}]", section_name),
+            }),
+        }
+    }
+    problems
+}
+
+fn get_position(content: &str, offset: usize) -> (usize, usize) {
+    let before = &content[..offset.min(content.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.rsplit('\n').next().unwrap_or("").chars().count() + 1;
+    (line, column)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn problems(filename: &str, content: &str) -> Vec<String> {
+        check_config_file(filename, content)
+            .into_iter()
+            .map(|problem| problem.message)
+            .collect()
+    }
+
+    #[test]
+    fn valid_config_files_have_no_problems() {
+        let content = r#"
+[remap]
+KEY_CAPSLOCK = { tap = ["KEY_ESC"], hold = ["KEY_LEFTCTRL"] }
+KEY_LEFTCTRL-KEY_J = ["KEY_DOWN"]
+"KEY_J+KEY_K" = ["KEY_ESC"]
+"KEY_SPACE KEY_F" = ["KEY_F11"]
+BTN_MODE_DOUBLE_TAP = ["KEY_HOMEPAGE"]
+
+[commands]
+KEY_F1 = ["notify-send hello"]
+
+[settings]
+CHAIN_ONLY = "true"
+LSTICK_SENSITIVITY = "6"
+"#;
+        assert_eq!(problems("Keyboard::2.toml", content), Vec::<String>::new());
+    }
+
+    #[test]
+    fn config_files_report_each_problem_with_its_position() {
+        let content = "[remap]\nKEY_A = [\"KEY_B\"]\nKEY_NOPE = [\"KEY_B\"]\n";
+        let problems = find_problems("Keyboard.toml", content);
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].starts_with("Keyboard.toml:3:1: "));
+    }
+
+    #[test]
+    fn config_files_report_invalid_settings_and_bindings() {
+        let content = r#"
+[remap]
+KEY_A = ["KEY_NOPE"]
+"KEY_J+KEY_J" = ["KEY_ESC"]
+KEY_LEFTCTRL_LONG_PRESS-KEY_C = ["KEY_D"]
+
+[settings]
+LSTICK_SENSITIVITY = "-1"
+NOT_A_SETTING = "1"
+
+[unknown]
+"#;
+        assert_eq!(problems("Keyboard.toml", content).len(), 6);
+    }
+
+    #[test]
+    fn config_files_report_syntax_errors_and_bad_file_names() {
+        assert_eq!(
+            problems(
+                "Keyboard.toml",
+                "[remap
+"
+            )
+            .len(),
+            1
+        );
+        assert_eq!(problems("Keyboard::a::b.toml", "").len(), 1);
+        assert_eq!(problems("Keyboard::::1.toml", "").len(), 1);
+    }
+
+    fn layout_names(names: &[(&str, Option<&str>)]) -> Vec<(String, Option<String>)> {
+        names
+            .iter()
+            .map(|(name, layout_name)| (name.to_string(), layout_name.map(str::to_string)))
+            .collect()
+    }
+
+    #[test]
+    fn layout_names_can_be_shared_by_files_of_the_same_layout() {
+        let names = layout_names(&[
+            ("Pad::1", Some("gaming")),
+            ("Pad::1::firefox", Some("gaming")),
+            ("Keyboard::2", Some("gaming")),
+        ]);
+        assert!(find_layout_name_problems("Pad::1", Some("gaming"), &names).is_empty());
+        assert!(find_layout_name_problems("Keyboard::2", Some("gaming"), &names).is_empty());
+    }
+
+    #[test]
+    fn layout_names_must_be_unique_per_device() {
+        let names = layout_names(&[("Pad::1", Some("gaming")), ("Pad::2", Some("gaming"))]);
+        assert_eq!(
+            find_layout_name_problems("Pad::2", Some("gaming"), &names),
+            vec!["Pad::2.toml: layout name \"gaming\" is already used by layout 1 in Pad::1.toml"]
+        );
+    }
+
+    #[test]
+    fn layout_names_in_file_names_are_reported() {
+        let names = layout_names(&[("Pad::1", Some("gaming")), ("Pad::gaming", None)]);
+        assert_eq!(
+            find_layout_name_problems("Pad::gaming", None, &names),
+            vec!["Pad::gaming.toml: \"gaming\" in the file name is read as a window class, not as a layout name, use the layout number 1 instead"]
+        );
+        assert!(find_layout_name_problems("Pad::firefox", None, &names).is_empty());
+    }
+}
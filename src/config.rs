@@ -1,5 +1,6 @@
 use crate::udev_monitor::Client;
-use evdev::Key;
+use evdev::{AbsoluteAxisType, Key};
+use regex::Regex;
 use serde;
 use std::{collections::HashMap, str::FromStr};
 
@@ -7,6 +8,9 @@ use std::{collections::HashMap, str::FromStr};
 pub enum Event {
     Axis(Axis),
     Key(Key),
+    DoubleTap(Key),
+    LongPress(Key),
+    Sequence(u16),
     Hold,
 }
 
@@ -31,6 +35,20 @@ pub enum Axis {
     ABS_RZ,
     ABS_WHEEL_CW,
     ABS_WHEEL_CCW,
+    SWIPE_3_UP,
+    SWIPE_3_DOWN,
+    SWIPE_3_LEFT,
+    SWIPE_3_RIGHT,
+    SWIPE_4_UP,
+    SWIPE_4_DOWN,
+    SWIPE_4_LEFT,
+    SWIPE_4_RIGHT,
+    PINCH_IN,
+    PINCH_OUT,
+    EDGE_SWIPE_LEFT,
+    EDGE_SWIPE_RIGHT,
+    EDGE_SWIPE_TOP,
+    EDGE_SWIPE_BOTTOM,
 }
 
 impl FromStr for Axis {
@@ -55,6 +73,20 @@ impl FromStr for Axis {
             "ABS_RZ" => Ok(Axis::ABS_RZ),
             "ABS_WHEEL_CW" => Ok(Axis::ABS_WHEEL_CW),
             "ABS_WHEEL_CCW" => Ok(Axis::ABS_WHEEL_CCW),
+            "SWIPE_3_UP" => Ok(Axis::SWIPE_3_UP),
+            "SWIPE_3_DOWN" => Ok(Axis::SWIPE_3_DOWN),
+            "SWIPE_3_LEFT" => Ok(Axis::SWIPE_3_LEFT),
+            "SWIPE_3_RIGHT" => Ok(Axis::SWIPE_3_RIGHT),
+            "SWIPE_4_UP" => Ok(Axis::SWIPE_4_UP),
+            "SWIPE_4_DOWN" => Ok(Axis::SWIPE_4_DOWN),
+            "SWIPE_4_LEFT" => Ok(Axis::SWIPE_4_LEFT),
+            "SWIPE_4_RIGHT" => Ok(Axis::SWIPE_4_RIGHT),
+            "PINCH_IN" => Ok(Axis::PINCH_IN),
+            "PINCH_OUT" => Ok(Axis::PINCH_OUT),
+            "EDGE_SWIPE_LEFT" => Ok(Axis::EDGE_SWIPE_LEFT),
+            "EDGE_SWIPE_RIGHT" => Ok(Axis::EDGE_SWIPE_RIGHT),
+            "EDGE_SWIPE_TOP" => Ok(Axis::EDGE_SWIPE_TOP),
+            "EDGE_SWIPE_BOTTOM" => Ok(Axis::EDGE_SWIPE_BOTTOM),
             _ => Err(s.to_string()),
         }
     }
@@ -107,13 +139,277 @@ pub struct Associations {
     pub layout: u16,
 }
 
+#[derive(Debug, Clone)]
+pub struct TapHold {
+    pub tap: Vec<Key>,
+    pub hold: Vec<Key>,
+    pub timeout: Option<u64>,
+    pub permissive_hold: Option<bool>,
+}
+
+#[derive(Debug, Clone)]
+pub enum MacroStep {
+    Press(Vec<Key>),
+    Release(Vec<Key>),
+    Tap(Vec<Key>),
+    Sleep(u64),
+    Text(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct Macro {
+    pub steps: Vec<MacroStep>,
+    pub repeat: u64,
+}
+
+#[derive(Debug, Clone)]
+pub enum LayoutId {
+    Number(u16),
+    Name(String),
+}
+
+impl LayoutId {
+    pub fn new(layout: &str) -> Self {
+        match layout.parse::<u16>() {
+            Ok(layout) => LayoutId::Number(layout),
+            Err(_) => LayoutId::Name(layout.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum LayoutAction {
+    Switch(LayoutId),
+    Previous,
+    Hold(LayoutId),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GamepadTarget {
+    Button(Key),
+    Axis(AbsoluteAxisType, i32),
+    Trigger(AbsoluteAxisType),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct GamepadOutput {
+    pub target: GamepadTarget,
+    pub magnitude: f64,
+}
+
+#[derive(Debug, Clone)]
+pub enum Toggle {
+    Keys(Vec<Key>),
+    Movement(Relative),
+}
+
+#[derive(Debug, Clone)]
+pub struct Turbo {
+    pub keys: Vec<Key>,
+    pub rate: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Rumble {
+    pub duration: u16,
+    pub strength: f64,
+}
+
+pub const GAMEPAD_BUTTONS: [Key; 11] = [
+    Key::BTN_SOUTH,
+    Key::BTN_EAST,
+    Key::BTN_NORTH,
+    Key::BTN_WEST,
+    Key::BTN_TL,
+    Key::BTN_TR,
+    Key::BTN_SELECT,
+    Key::BTN_START,
+    Key::BTN_MODE,
+    Key::BTN_THUMBL,
+    Key::BTN_THUMBR,
+];
+
+impl FromStr for GamepadTarget {
+    type Err = String;
+    fn from_str(s: &str) -> Result<GamepadTarget, Self::Err> {
+        match s {
+            "LSTICK_UP" => Ok(GamepadTarget::Axis(AbsoluteAxisType::ABS_Y, -1)),
+            "LSTICK_DOWN" => Ok(GamepadTarget::Axis(AbsoluteAxisType::ABS_Y, 1)),
+            "LSTICK_LEFT" => Ok(GamepadTarget::Axis(AbsoluteAxisType::ABS_X, -1)),
+            "LSTICK_RIGHT" => Ok(GamepadTarget::Axis(AbsoluteAxisType::ABS_X, 1)),
+            "RSTICK_UP" => Ok(GamepadTarget::Axis(AbsoluteAxisType::ABS_RY, -1)),
+            "RSTICK_DOWN" => Ok(GamepadTarget::Axis(AbsoluteAxisType::ABS_RY, 1)),
+            "RSTICK_LEFT" => Ok(GamepadTarget::Axis(AbsoluteAxisType::ABS_RX, -1)),
+            "RSTICK_RIGHT" => Ok(GamepadTarget::Axis(AbsoluteAxisType::ABS_RX, 1)),
+            "BTN_DPAD_UP" => Ok(GamepadTarget::Axis(AbsoluteAxisType::ABS_HAT0Y, -1)),
+            "BTN_DPAD_DOWN" => Ok(GamepadTarget::Axis(AbsoluteAxisType::ABS_HAT0Y, 1)),
+            "BTN_DPAD_LEFT" => Ok(GamepadTarget::Axis(AbsoluteAxisType::ABS_HAT0X, -1)),
+            "BTN_DPAD_RIGHT" => Ok(GamepadTarget::Axis(AbsoluteAxisType::ABS_HAT0X, 1)),
+            "ABS_Z" => Ok(GamepadTarget::Trigger(AbsoluteAxisType::ABS_Z)),
+            "ABS_RZ" => Ok(GamepadTarget::Trigger(AbsoluteAxisType::ABS_RZ)),
+            "BTN_A" => Ok(GamepadTarget::Button(Key::BTN_SOUTH)),
+            "BTN_B" => Ok(GamepadTarget::Button(Key::BTN_EAST)),
+            "BTN_X" => Ok(GamepadTarget::Button(Key::BTN_NORTH)),
+            "BTN_Y" => Ok(GamepadTarget::Button(Key::BTN_WEST)),
+            _ => match Key::from_str(s) {
+                Ok(key) if GAMEPAD_BUTTONS.contains(&key) => Ok(GamepadTarget::Button(key)),
+                _ => Err(format!("unknown gamepad target {:?}", s)),
+            },
+        }
+    }
+}
+
+// exp(steepness) overflows to infinity above ~709 and turns the curve into NaN,
+// while past 50 the curve is already flat up to the very edge.
+const MAX_CURVE_STEEPNESS: f64 = 50.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Curve {
+    Linear,
+    Power(f64),
+    Exponential(f64),
+    Points(Vec<(f64, f64)>),
+}
+
+impl FromStr for Curve {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Curve, Self::Err> {
+        let parse_number = |number: &str| match number.trim().parse::<f64>() {
+            Ok(number) if (0.0..=1000.0).contains(&number) => Ok(number),
+            _ => Err(format!("invalid number {:?} in curve {:?}", number, s)),
+        };
+        if s.trim() == "linear" {
+            return Ok(Curve::Linear);
+        }
+        match s.trim().split_once(":") {
+            Some(("power", exponent)) => match parse_number(exponent)? {
+                exponent if exponent > 0.0 => Ok(Curve::Power(exponent)),
+                _ => Err(format!("the exponent of curve {:?} must be > 0", s)),
+            },
+            Some(("exponential", steepness)) => match parse_number(steepness)? {
+                steepness if steepness > 0.0 && steepness <= MAX_CURVE_STEEPNESS => {
+                    Ok(Curve::Exponential(steepness))
+                }
+                _ => Err(format!(
+                    "the steepness of curve {:?} must be > 0 and <= {}",
+                    s, MAX_CURVE_STEEPNESS
+                )),
+            },
+            Some(_) => Err(format!("invalid curve {:?}", s)),
+            None => {
+                let mut points: Vec<(f64, f64)> = Vec::new();
+                for point in s.split_whitespace() {
+                    let Some((input, output)) = point.split_once(",") else {
+                        return Err(format!("invalid curve point {:?}", point));
+                    };
+                    let (input, output) = (parse_number(input)?, parse_number(output)?);
+                    if input == 0.0
+                        || input > 1.0
+                        || output > 1.0
+                        || points.last().is_some_and(|last| last.0 >= input)
+                    {
+                        return Err(format!(
+                            "curve points must be between 0 and 1 and in increasing order, found {:?}",
+                            point
+                        ));
+                    }
+                    points.push((input, output));
+                }
+                if points.is_empty() {
+                    return Err(format!("invalid curve {:?}", s));
+                }
+                Ok(Curve::Points(points))
+            }
+        }
+    }
+}
+
+impl Curve {
+    pub fn apply(&self, input: f64) -> f64 {
+        let input = input.clamp(0.0, 1.0);
+        match self {
+            Curve::Linear => input,
+            Curve::Power(exponent) => input.powf(*exponent),
+            Curve::Exponential(steepness) => {
+                ((steepness * input).exp() - 1.0) / (steepness.exp() - 1.0)
+            }
+            Curve::Points(points) => {
+                let mut previous = (0.0, 0.0);
+                for &point in points.iter().chain([(1.0, 1.0)].iter()) {
+                    if input <= point.0 {
+                        return previous.1
+                            + (point.1 - previous.1) * (input - previous.0)
+                                / (point.0 - previous.0);
+                    }
+                    previous = point;
+                }
+                previous.1
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum RemapAction {
+    Keys(Vec<Key>),
+    TapHold(TapHold),
+    Macro(Macro),
+    Layout(LayoutAction),
+    Gamepad(GamepadOutput),
+    Rumble(Rumble),
+    Turbo(Turbo),
+    ToggleTurbo(Event),
+    Toggle(Vec<Key>),
+}
+
 #[derive(Default, Debug, Clone)]
 pub struct Bindings {
     pub remap: HashMap<Event, HashMap<Vec<Event>, Vec<Key>>>,
+    pub tap_hold: HashMap<Event, HashMap<Vec<Event>, TapHold>>,
+    pub macros: HashMap<Event, HashMap<Vec<Event>, Macro>>,
+    pub layouts: HashMap<Event, HashMap<Vec<Event>, LayoutAction>>,
+    pub gamepad: HashMap<Event, HashMap<Vec<Event>, GamepadOutput>>,
+    pub rumble: HashMap<Event, HashMap<Vec<Event>, Rumble>>,
+    pub turbo: HashMap<Event, HashMap<Vec<Event>, Turbo>>,
+    pub turbo_toggles: HashMap<Event, HashMap<Vec<Event>, Event>>,
+    pub toggles: HashMap<Event, HashMap<Vec<Event>, Toggle>>,
+    pub chords: HashMap<Vec<Event>, HashMap<Vec<Event>, Vec<Key>>>,
+    pub sequences: Vec<Vec<Event>>,
     pub commands: HashMap<Event, HashMap<Vec<Event>, Vec<String>>>,
     pub movements: HashMap<Event, HashMap<Vec<Event>, Relative>>,
 }
 
+impl Bindings {
+    pub fn events(&self) -> impl Iterator<Item = &Event> {
+        self.remap
+            .keys()
+            .chain(self.tap_hold.keys())
+            .chain(self.macros.keys())
+            .chain(self.layouts.keys())
+            .chain(self.gamepad.keys())
+            .chain(self.rumble.keys())
+            .chain(self.turbo.keys())
+            .chain(self.turbo_toggles.keys())
+            .chain(self.toggles.keys())
+            .chain(self.commands.keys())
+            .chain(self.movements.keys())
+    }
+
+    pub fn contains(&self, event: &Event) -> bool {
+        self.remap.contains_key(event)
+            || self.tap_hold.contains_key(event)
+            || self.macros.contains_key(event)
+            || self.layouts.contains_key(event)
+            || self.gamepad.contains_key(event)
+            || self.rumble.contains_key(event)
+            || self.turbo.contains_key(event)
+            || self.turbo_toggles.contains_key(event)
+            || self.toggles.contains_key(event)
+            || self.commands.contains_key(event)
+            || self.movements.contains_key(event)
+    }
+}
+
 #[derive(Default, Debug, Clone)]
 pub struct MappedModifiers {
     pub default: Vec<Event>,
@@ -121,43 +417,144 @@ pub struct MappedModifiers {
     pub all: Vec<Event>,
 }
 
+#[derive(Default, Debug, Clone)]
+pub struct DeviceMatch {
+    pub vendor: Option<u16>,
+    pub product: Option<u16>,
+    pub bustype: Option<u16>,
+    pub phys: Option<String>,
+    pub uniq: Option<String>,
+    pub name: Option<Regex>,
+}
+
+impl DeviceMatch {
+    pub fn new(table: &HashMap<String, toml::Value>) -> Result<Self, String> {
+        if table.is_empty() {
+            return Err("the [device] table is empty".to_string());
+        }
+        let mut device_match: DeviceMatch = Default::default();
+        for (field, value) in table {
+            device_match.set_field(field, value)?;
+        }
+        Ok(device_match)
+    }
+
+    pub fn set_field(&mut self, field: &str, value: &toml::Value) -> Result<(), String> {
+        match field {
+            "vendor" => self.vendor = Some(parse_id(field, value)?),
+            "product" => self.product = Some(parse_id(field, value)?),
+            "bustype" => {
+                self.bustype = Some(match value.as_str() {
+                    Some("usb") => 0x03,
+                    Some("bluetooth") => 0x05,
+                    Some("virtual") => 0x06,
+                    Some("i8042") => 0x11,
+                    Some("host") => 0x19,
+                    _ => parse_id(field, value)?,
+                })
+            }
+            "phys" | "uniq" => {
+                let Some(value) = value.as_str() else {
+                    return Err(format!("{} in [device] must be a string", field));
+                };
+                if field == "phys" {
+                    self.phys = Some(value.to_string())
+                } else {
+                    self.uniq = Some(value.to_string())
+                }
+            }
+            "name" => {
+                let Some(value) = value.as_str() else {
+                    return Err("name in [device] must be a string".to_string());
+                };
+                self.name = Some(Regex::new(value).map_err(|error| {
+                    format!("invalid regular expression for name in [device]: {}", error)
+                })?)
+            }
+            _ => return Err(format!("unknown field {:?} in [device]", field)),
+        };
+        Ok(())
+    }
+
+    pub fn matches(&self, device: &evdev::Device) -> bool {
+        let input_id = device.input_id();
+        self.vendor.is_none_or(|vendor| vendor == input_id.vendor())
+            && self
+                .product
+                .is_none_or(|product| product == input_id.product())
+            && self
+                .bustype
+                .is_none_or(|bustype| bustype == input_id.bus_type().0)
+            && self
+                .phys
+                .as_ref()
+                .is_none_or(|phys| Some(phys.as_str()) == device.physical_path())
+            && self
+                .uniq
+                .as_ref()
+                .is_none_or(|uniq| Some(uniq.as_str()) == device.unique_name())
+            && self
+                .name
+                .as_ref()
+                .is_none_or(|name| name.is_match(device.name().unwrap_or_default()))
+    }
+}
+
+fn parse_id(field: &str, value: &toml::Value) -> Result<u16, String> {
+    let id = match value {
+        toml::Value::Integer(id) => u16::try_from(*id).ok(),
+        toml::Value::String(id) => u16::from_str_radix(id.trim_start_matches("0x"), 16).ok(),
+        _ => None,
+    };
+    id.ok_or(format!(
+        "{} in [device] must be a 16 bit number, e.g. 0x054c or \"054c\"",
+        field
+    ))
+}
+
 #[derive(serde::Deserialize, Debug, Clone)]
 pub struct RawConfig {
     #[serde(default)]
-    pub remap: HashMap<String, Vec<Key>>,
+    pub device: Option<HashMap<String, toml::Value>>,
+    #[serde(default)]
+    pub remap: HashMap<String, toml::Value>,
     #[serde(default)]
-    pub commands: HashMap<String, Vec<String>>,
+    pub commands: HashMap<String, toml::Value>,
     #[serde(default)]
-    pub movements: HashMap<String, String>,
+    pub movements: HashMap<String, toml::Value>,
     #[serde(default)]
     pub settings: HashMap<String, String>,
 }
 
 impl RawConfig {
-    fn new_from_file(file: &str) -> Self {
+    fn new_from_file(file: &str) -> Result<Self, String> {
         println!(
             "Parsing config file:\n{:?}\n",
             file.rsplit_once("/").unwrap().1
         );
-        let file_content: String = std::fs::read_to_string(file).unwrap();
+        let file_content: String =
+            std::fs::read_to_string(file).map_err(|error| error.to_string())?;
         let raw_config: RawConfig =
-            toml::from_str(&file_content).expect("Couldn't parse config file.");
+            toml::from_str(&file_content).map_err(|error| error.to_string())?;
+        let device = raw_config.device;
         let remap = raw_config.remap;
         let commands = raw_config.commands;
         let movements = raw_config.movements;
         let settings = raw_config.settings;
-        Self {
+        Ok(Self {
+            device,
             remap,
             commands,
             movements,
             settings,
-        }
+        })
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct Config {
     pub name: String,
+    pub device: Option<DeviceMatch>,
     pub associations: Associations,
     pub bindings: Bindings,
     pub settings: HashMap<String, String>,
@@ -165,23 +562,37 @@ pub struct Config {
 }
 
 impl Config {
-    pub fn new_from_file(file: &str, file_name: String) -> Self {
-        let raw_config = RawConfig::new_from_file(file);
+    pub fn uses_gamepad(&self) -> bool {
+        !self.bindings.gamepad.is_empty()
+            || self
+                .settings
+                .get("MOUSE_TO_STICK")
+                .is_some_and(|stick| stick != "disabled")
+    }
+
+    pub fn new_from_file(file: &str, file_name: String) -> Result<Self, String> {
+        let raw_config = RawConfig::new_from_file(file)?;
+        let device = match &raw_config.device {
+            Some(table) => Some(DeviceMatch::new(table)?),
+            None => None,
+        };
         let (bindings, settings, mapped_modifiers) = parse_raw_config(raw_config);
         let associations = Default::default();
 
-        Self {
+        Ok(Self {
             name: file_name,
+            device,
             associations,
             bindings,
             settings,
             mapped_modifiers,
-        }
+        })
     }
 
     pub fn new_empty(file_name: String) -> Self {
         Self {
             name: file_name,
+            device: None,
             associations: Default::default(),
             bindings: Default::default(),
             settings: Default::default(),
@@ -190,10 +601,57 @@ impl Config {
     }
 }
 
+pub fn is_config_file(filename: &str) -> bool {
+    filename.ends_with(".toml") && !filename.starts_with(".")
+}
+
+pub fn is_associated(config: &Config, config_files: &[Config], device: &evdev::Device) -> bool {
+    let device_name = config.name.split("::").collect::<Vec<&str>>()[0];
+    let device_match = config.device.as_ref().or_else(|| {
+        config_files
+            .iter()
+            .find(|x| {
+                x.name.split("::").collect::<Vec<&str>>()[0] == device_name && x.device.is_some()
+            })
+            .and_then(|x| x.device.as_ref())
+    });
+    match device_match {
+        Some(device_match) => device_match.matches(device),
+        None => device_name == device.name().unwrap_or_default().replace("/", ""),
+    }
+}
+
+pub fn parse_associations(config_name: &str) -> Result<(Client, u16), String> {
+    let split_config_name = config_name.split("::").collect::<Vec<&str>>();
+    match split_config_name.len() {
+        1 => Ok((Client::Default, 0)),
+        2 => {
+            if let Ok(layout) = split_config_name[1].parse::<u16>() {
+                Ok((Client::Default, layout))
+            } else {
+                Ok((Client::Class(split_config_name[1].to_string()), 0))
+            }
+        }
+        3 => {
+            if let Ok(layout) = split_config_name[1].parse::<u16>() {
+                Ok((Client::Class(split_config_name[2].to_string()), layout))
+            } else if let Ok(layout) = split_config_name[2].parse::<u16>() {
+                Ok((Client::Class(split_config_name[1].to_string()), layout))
+            } else {
+                Err(format!("unable to parse layout number in {}", config_name))
+            }
+        }
+        _ => Err(format!(
+            "too many arguments in config file name {}",
+            config_name
+        )),
+    }
+}
+
 fn parse_raw_config(raw_config: RawConfig) -> (Bindings, HashMap<String, String>, MappedModifiers) {
-    let remap: HashMap<String, Vec<Key>> = raw_config.remap;
-    let commands: HashMap<String, Vec<String>> = raw_config.commands;
-    let movements: HashMap<String, String> = raw_config.movements;
+    let remap: HashMap<String, toml::Value> = raw_config.remap;
+    let commands: HashMap<String, toml::Value> = raw_config.commands;
+    let movements: HashMap<String, toml::Value> = raw_config.movements;
     let settings: HashMap<String, String> = raw_config.settings;
     let mut bindings: Bindings = Default::default();
     let default_modifiers = vec![
@@ -220,264 +678,149 @@ fn parse_raw_config(raw_config: RawConfig) -> (Bindings, HashMap<String, String>
     mapped_modifiers.custom.extend(lstick_activation_modifiers);
     mapped_modifiers.custom.extend(rstick_activation_modifiers);
 
-    for (input, output) in remap.clone() {
-        if let Some((mods, event)) = input.rsplit_once("-") {
-            let str_modifiers = mods.split("-").collect::<Vec<&str>>();
-            let mut modifiers: Vec<Event> = Vec::new();
-            for event in str_modifiers.clone() {
-                if let Ok(axis) = Axis::from_str(event) {
-                    modifiers.push(Event::Axis(axis));
-                } else if let Ok(key) = Key::from_str(event) {
-                    modifiers.push(Event::Key(key));
-                }
-            }
-            modifiers.sort();
-            modifiers.dedup();
-            for modifier in &modifiers {
-                if !mapped_modifiers.default.contains(&modifier) {
-                    mapped_modifiers.custom.push(modifier.clone());
-                }
-            }
-            if str_modifiers[0] == "" {
-                modifiers.push(Event::Hold);
-            }
-            if let Ok(event) = Axis::from_str(event) {
-                if !bindings.remap.contains_key(&Event::Axis(event)) {
-                    bindings
-                        .remap
-                        .insert(Event::Axis(event), HashMap::from([(modifiers, output)]));
-                } else {
-                    bindings
-                        .remap
-                        .get_mut(&Event::Axis(event))
-                        .unwrap()
-                        .insert(modifiers, output);
-                }
-            } else if let Ok(event) = Key::from_str(event) {
-                if !bindings.remap.contains_key(&Event::Key(event)) {
-                    bindings
-                        .remap
-                        .insert(Event::Key(event), HashMap::from([(modifiers, output)]));
-                } else {
+    for (input, output) in remap {
+        if input.contains("+") {
+            match (parse_chord(&input), parse_chord_remap(&output)) {
+                (Ok((keys, modifiers)), Ok(output)) => {
+                    map_custom_modifiers(&modifiers, &mut mapped_modifiers);
                     bindings
-                        .remap
-                        .get_mut(&Event::Key(event))
-                        .unwrap()
+                        .chords
+                        .entry(keys)
+                        .or_default()
                         .insert(modifiers, output);
                 }
+                (Err(error), _) | (_, Err(error)) => println!(
+                    "Warning: {} in [remap] binding {:?}, ignoring it.\n",
+                    error, input
+                ),
             }
-        } else {
-            let modifiers: Vec<Event> = Vec::new();
-            if let Ok(event) = Axis::from_str(input.as_str()) {
-                if !bindings.remap.contains_key(&Event::Axis(event)) {
-                    bindings
-                        .remap
-                        .insert(Event::Axis(event), HashMap::from([(modifiers, output)]));
-                } else {
-                    bindings
-                        .remap
-                        .get_mut(&Event::Axis(event))
-                        .unwrap()
-                        .insert(modifiers, output);
-                }
-            } else if let Ok(event) = Key::from_str(input.as_str()) {
-                if !bindings.remap.contains_key(&Event::Key(event)) {
-                    bindings
-                        .remap
-                        .insert(Event::Key(event), HashMap::from([(modifiers, output)]));
-                } else {
-                    bindings
-                        .remap
-                        .get_mut(&Event::Key(event))
-                        .unwrap()
-                        .insert(modifiers, output);
+            continue;
+        }
+        match (
+            parse_trigger(&input, &mut bindings.sequences),
+            parse_remap(&output),
+        ) {
+            (Ok((event, modifiers)), Ok(output)) => {
+                map_custom_modifiers(&modifiers, &mut mapped_modifiers);
+                match output {
+                    RemapAction::Keys(output) => {
+                        bindings
+                            .remap
+                            .entry(event)
+                            .or_default()
+                            .insert(modifiers, output);
+                    }
+                    RemapAction::TapHold(output) => {
+                        bindings
+                            .tap_hold
+                            .entry(event)
+                            .or_default()
+                            .insert(modifiers, output);
+                    }
+                    RemapAction::Macro(output) => {
+                        bindings
+                            .macros
+                            .entry(event)
+                            .or_default()
+                            .insert(modifiers, output);
+                    }
+                    RemapAction::Layout(output) => {
+                        bindings
+                            .layouts
+                            .entry(event)
+                            .or_default()
+                            .insert(modifiers, output);
+                    }
+                    RemapAction::Gamepad(output) => {
+                        bindings
+                            .gamepad
+                            .entry(event)
+                            .or_default()
+                            .insert(modifiers, output);
+                    }
+                    RemapAction::Rumble(output) => {
+                        bindings
+                            .rumble
+                            .entry(event)
+                            .or_default()
+                            .insert(modifiers, output);
+                    }
+                    RemapAction::Turbo(output) => {
+                        bindings
+                            .turbo
+                            .entry(event)
+                            .or_default()
+                            .insert(modifiers, output);
+                    }
+                    RemapAction::ToggleTurbo(output) => {
+                        bindings
+                            .turbo_toggles
+                            .entry(event)
+                            .or_default()
+                            .insert(modifiers, output);
+                    }
+                    RemapAction::Toggle(output) => {
+                        bindings
+                            .toggles
+                            .entry(event)
+                            .or_default()
+                            .insert(modifiers, Toggle::Keys(output));
+                    }
                 }
             }
+            (Err(error), _) | (_, Err(error)) => println!(
+                "Warning: {} in [remap] binding {:?}, ignoring it.\n",
+                error, input
+            ),
         }
     }
 
-    for (input, output) in commands.clone() {
-        if let Some((mods, event)) = input.rsplit_once("-") {
-            let str_modifiers = mods.split("-").collect::<Vec<&str>>();
-            let mut modifiers: Vec<Event> = Vec::new();
-            for event in str_modifiers {
-                if let Ok(axis) = Axis::from_str(event) {
-                    modifiers.push(Event::Axis(axis));
-                } else if let Ok(key) = Key::from_str(event) {
-                    modifiers.push(Event::Key(key));
-                }
-            }
-            modifiers.sort();
-            modifiers.dedup();
-            for modifier in &modifiers {
-                if !mapped_modifiers.default.contains(&modifier) {
-                    mapped_modifiers.custom.push(modifier.clone());
-                }
-            }
-            if let Ok(event) = Axis::from_str(event) {
-                if !bindings.commands.contains_key(&Event::Axis(event)) {
-                    bindings
-                        .commands
-                        .insert(Event::Axis(event), HashMap::from([(modifiers, output)]));
-                } else {
-                    bindings
-                        .commands
-                        .get_mut(&Event::Axis(event))
-                        .unwrap()
-                        .insert(modifiers, output);
-                }
-            } else if let Ok(event) = Key::from_str(event) {
-                if !bindings.commands.contains_key(&Event::Key(event)) {
-                    bindings
-                        .commands
-                        .insert(Event::Key(event), HashMap::from([(modifiers, output)]));
-                } else {
-                    bindings
-                        .commands
-                        .get_mut(&Event::Key(event))
-                        .unwrap()
-                        .insert(modifiers, output);
-                }
-            }
-        } else {
-            let modifiers: Vec<Event> = Vec::new();
-            if let Ok(event) = Axis::from_str(input.as_str()) {
-                if !bindings.commands.contains_key(&Event::Axis(event)) {
-                    bindings
-                        .commands
-                        .insert(Event::Axis(event), HashMap::from([(modifiers, output)]));
-                } else {
-                    bindings
-                        .commands
-                        .get_mut(&Event::Axis(event))
-                        .unwrap()
-                        .insert(modifiers, output);
-                }
-            } else if let Ok(event) = Key::from_str(input.as_str()) {
-                if !bindings.commands.contains_key(&Event::Key(event)) {
-                    bindings
-                        .commands
-                        .insert(Event::Key(event), HashMap::from([(modifiers, output)]));
-                } else {
-                    bindings
-                        .commands
-                        .get_mut(&Event::Key(event))
-                        .unwrap()
-                        .insert(modifiers, output);
-                }
+    for (input, output) in commands {
+        match (
+            parse_trigger(&input, &mut bindings.sequences),
+            parse_commands(&output),
+        ) {
+            (Ok((event, mut modifiers)), Ok(output)) => {
+                modifiers.retain(|&modifier| modifier != Event::Hold);
+                map_custom_modifiers(&modifiers, &mut mapped_modifiers);
+                bindings
+                    .commands
+                    .entry(event)
+                    .or_default()
+                    .insert(modifiers, output);
             }
+            (Err(error), _) | (_, Err(error)) => println!(
+                "Warning: {} in [commands] binding {:?}, ignoring it.\n",
+                error, input
+            ),
         }
     }
 
-    for (input, output) in movements.clone() {
-        if let Some((mods, event)) = input.rsplit_once("-") {
-            let str_modifiers = mods.split("-").collect::<Vec<&str>>();
-            let mut modifiers: Vec<Event> = Vec::new();
-            for event in str_modifiers.clone() {
-                if let Ok(axis) = Axis::from_str(event) {
-                    modifiers.push(Event::Axis(axis));
-                } else if let Ok(key) = Key::from_str(event) {
-                    modifiers.push(Event::Key(key));
-                }
-            }
-            modifiers.sort();
-            modifiers.dedup();
-            for modifier in &modifiers {
-                if !mapped_modifiers.default.contains(&modifier) {
-                    mapped_modifiers.custom.push(modifier.clone());
-                }
-            }
-            if str_modifiers[0] == "" {
-                modifiers.push(Event::Hold);
-            }
-            if let Ok(event) = Axis::from_str(event) {
-                if !bindings.movements.contains_key(&Event::Axis(event)) {
-                    bindings.movements.insert(
-                        Event::Axis(event),
-                        HashMap::from([(
-                            modifiers,
-                            Relative::from_str(output.as_str())
-                                .expect("Invalid movement in [movements]."),
-                        )]),
-                    );
-                } else {
-                    bindings
-                        .movements
-                        .get_mut(&Event::Axis(event))
-                        .unwrap()
-                        .insert(
-                            modifiers,
-                            Relative::from_str(output.as_str())
-                                .expect("Invalid movement in [movements]."),
-                        );
-                }
-            } else if let Ok(event) = Key::from_str(event) {
-                if !bindings.movements.contains_key(&Event::Key(event)) {
-                    bindings.movements.insert(
-                        Event::Key(event),
-                        HashMap::from([(
-                            modifiers,
-                            Relative::from_str(output.as_str())
-                                .expect("Invalid movement in [movements]."),
-                        )]),
-                    );
-                } else {
-                    bindings
-                        .movements
-                        .get_mut(&Event::Key(event))
-                        .unwrap()
-                        .insert(
-                            modifiers,
-                            Relative::from_str(output.as_str())
-                                .expect("Invalid movement in [movements]."),
-                        );
-                }
-            }
-        } else {
-            let modifiers: Vec<Event> = Vec::new();
-            if let Ok(event) = Axis::from_str(input.as_str()) {
-                if !bindings.movements.contains_key(&Event::Axis(event)) {
-                    bindings.movements.insert(
-                        Event::Axis(event),
-                        HashMap::from([(
-                            modifiers,
-                            Relative::from_str(output.as_str())
-                                .expect("Invalid movement in [movements]."),
-                        )]),
-                    );
-                } else {
+    for (input, output) in movements {
+        match (
+            parse_trigger(&input, &mut bindings.sequences),
+            parse_movement(&output),
+        ) {
+            (Ok((event, modifiers)), Ok((output, toggle))) => {
+                map_custom_modifiers(&modifiers, &mut mapped_modifiers);
+                if toggle {
                     bindings
-                        .movements
-                        .get_mut(&Event::Axis(event))
-                        .unwrap()
-                        .insert(
-                            modifiers,
-                            Relative::from_str(output.as_str())
-                                .expect("Invalid movement in [movements]."),
-                        );
-                }
-            } else if let Ok(event) = Key::from_str(input.as_str()) {
-                if !bindings.movements.contains_key(&Event::Key(event)) {
-                    bindings.movements.insert(
-                        Event::Key(event),
-                        HashMap::from([(
-                            modifiers,
-                            Relative::from_str(output.as_str())
-                                .expect("Invalid movement in [movements]."),
-                        )]),
-                    );
+                        .toggles
+                        .entry(event)
+                        .or_default()
+                        .insert(modifiers, Toggle::Movement(output));
                 } else {
                     bindings
                         .movements
-                        .get_mut(&Event::Key(event))
-                        .unwrap()
-                        .insert(
-                            modifiers,
-                            Relative::from_str(output.as_str())
-                                .expect("Invalid movement in [movements]."),
-                        );
+                        .entry(event)
+                        .or_default()
+                        .insert(modifiers, output);
                 }
             }
+            (Err(error), _) | (_, Err(error)) => println!(
+                "Warning: {} in [movements] binding {:?}, ignoring it.\n",
+                error, input
+            ),
         }
     }
 
@@ -493,6 +836,504 @@ fn parse_raw_config(raw_config: RawConfig) -> (Bindings, HashMap<String, String>
     (bindings, settings, mapped_modifiers)
 }
 
+fn map_custom_modifiers(modifiers: &Vec<Event>, mapped_modifiers: &mut MappedModifiers) {
+    for modifier in modifiers {
+        if *modifier != Event::Hold && !mapped_modifiers.default.contains(modifier) {
+            mapped_modifiers.custom.push(*modifier);
+        }
+    }
+}
+
+pub fn parse_event(event: &str) -> Result<Event, String> {
+    if let Ok(axis) = Axis::from_str(event) {
+        Ok(Event::Axis(axis))
+    } else if let Ok(key) = Key::from_str(event) {
+        Ok(Event::Key(key))
+    } else if ["_DOUBLE_TAP", "_LONG_PRESS"].iter().any(|suffix| {
+        event
+            .strip_suffix(suffix)
+            .is_some_and(|key| Key::from_str(key).is_ok())
+    }) {
+        Err(format!(
+            "{:?} can only trigger a binding on its own, not as a modifier or in a chord or sequence",
+            event
+        ))
+    } else {
+        Err(format!("unknown key or axis name {:?}", event))
+    }
+}
+
+fn parse_trigger_event(event: &str) -> Result<Event, String> {
+    if let Some(Ok(key)) = event.strip_suffix("_DOUBLE_TAP").map(Key::from_str) {
+        Ok(Event::DoubleTap(key))
+    } else if let Some(Ok(key)) = event.strip_suffix("_LONG_PRESS").map(Key::from_str) {
+        Ok(Event::LongPress(key))
+    } else {
+        parse_event(event)
+    }
+}
+
+pub fn parse_binding(input: &str) -> Result<(Event, Vec<Event>), String> {
+    let Some((mods, event)) = input.rsplit_once("-") else {
+        return Ok((parse_trigger_event(input)?, Vec::new()));
+    };
+    Ok((parse_trigger_event(event)?, parse_binding_modifiers(mods)?))
+}
+
+fn parse_trigger(
+    input: &str,
+    sequences: &mut Vec<Vec<Event>>,
+) -> Result<(Event, Vec<Event>), String> {
+    if !input.contains(" ") {
+        return parse_binding(input);
+    }
+    let sequence = parse_sequence(input)?;
+    let id = match sequences.iter().position(|x| *x == sequence) {
+        Some(id) => id,
+        None => {
+            sequences.push(sequence);
+            sequences.len() - 1
+        }
+    };
+    Ok((Event::Sequence(id as u16), Vec::new()))
+}
+
+pub fn parse_sequence(input: &str) -> Result<Vec<Event>, String> {
+    let sequence = input
+        .split_whitespace()
+        .map(|step| match parse_event(step)? {
+            Event::Key(key) => Ok(Event::Key(key)),
+            _ => Err(format!("sequences can only contain keys, found {:?}", step)),
+        })
+        .collect::<Result<Vec<Event>, String>>()?;
+    if sequence.len() < 2 {
+        return Err("a sequence needs at least two keys".to_string());
+    }
+    Ok(sequence)
+}
+
+pub fn parse_chord(input: &str) -> Result<(Vec<Event>, Vec<Event>), String> {
+    let (modifiers, chord) = match input.rsplit_once("-") {
+        Some((mods, chord)) => (parse_binding_modifiers(mods)?, chord),
+        None => (Vec::new(), input),
+    };
+    let mut keys = chord
+        .split("+")
+        .map(parse_event)
+        .collect::<Result<Vec<Event>, String>>()?;
+    keys.sort();
+    keys.dedup();
+    if keys.len() < 2 {
+        return Err("a chord needs at least two different keys".to_string());
+    }
+    Ok((keys, modifiers))
+}
+
+pub fn parse_chord_remap(output: &toml::Value) -> Result<Vec<Key>, String> {
+    match parse_remap(output)? {
+        RemapAction::Keys(keys) => Ok(keys),
+        _ => Err("chords can only be remapped to a list of keys".to_string()),
+    }
+}
+
+fn parse_binding_modifiers(mods: &str) -> Result<Vec<Event>, String> {
+    let str_modifiers = mods.split("-").collect::<Vec<&str>>();
+    let mut modifiers: Vec<Event> = Vec::new();
+    for (index, modifier) in str_modifiers.iter().enumerate() {
+        if index == 0 && modifier.is_empty() {
+            continue;
+        }
+        modifiers.push(parse_event(modifier)?);
+    }
+    modifiers.sort();
+    modifiers.dedup();
+    if str_modifiers[0].is_empty() {
+        modifiers.push(Event::Hold);
+    }
+    Ok(modifiers)
+}
+
+pub fn parse_remap(output: &toml::Value) -> Result<RemapAction, String> {
+    match output {
+        toml::Value::Array(_) => Ok(RemapAction::Keys(parse_keys(output)?)),
+        toml::Value::String(target) => Ok(RemapAction::Gamepad(GamepadOutput {
+            target: GamepadTarget::from_str(target)?,
+            magnitude: 1.0,
+        })),
+        toml::Value::Table(table) if table.contains_key("gamepad") => {
+            check_fields(table, &["gamepad", "magnitude"])?;
+            let Some(target) = table["gamepad"].as_str() else {
+                return Err("gamepad must be a gamepad target".to_string());
+            };
+            let magnitude = match table.get("magnitude") {
+                Some(toml::Value::Float(magnitude)) if (0.0..=1.0).contains(magnitude) => {
+                    *magnitude
+                }
+                Some(toml::Value::Integer(magnitude)) if (0..=1).contains(magnitude) => {
+                    *magnitude as f64
+                }
+                Some(_) => return Err("magnitude must be a number between 0.0 and 1.0".to_string()),
+                None => 1.0,
+            };
+            Ok(RemapAction::Gamepad(GamepadOutput {
+                target: GamepadTarget::from_str(target)?,
+                magnitude,
+            }))
+        }
+        toml::Value::Table(table) if table.contains_key("tap") || table.contains_key("hold") => {
+            check_fields(table, &["tap", "hold", "timeout", "permissive_hold"])?;
+            let (Some(tap), Some(hold)) = (table.get("tap"), table.get("hold")) else {
+                return Err("tap-hold bindings need both \"tap\" and \"hold\"".to_string());
+            };
+            let timeout = match table.get("timeout") {
+                Some(toml::Value::Integer(timeout)) if *timeout >= 0 => Some(*timeout as u64),
+                Some(_) => return Err("timeout must be an integer >= 0".to_string()),
+                None => None,
+            };
+            let permissive_hold = match table.get("permissive_hold") {
+                Some(toml::Value::Boolean(permissive_hold)) => Some(*permissive_hold),
+                Some(_) => return Err("permissive_hold can only be true or false".to_string()),
+                None => None,
+            };
+            Ok(RemapAction::TapHold(TapHold {
+                tap: parse_keys(tap)?,
+                hold: parse_keys(hold)?,
+                timeout,
+                permissive_hold,
+            }))
+        }
+        toml::Value::Table(table) if table.contains_key("macro") => {
+            check_fields(table, &["macro", "repeat"])?;
+            let repeat = match table.get("repeat") {
+                Some(toml::Value::Integer(repeat)) if *repeat >= 0 => *repeat as u64,
+                Some(_) => return Err("repeat must be an integer >= 0".to_string()),
+                None => 1,
+            };
+            let Some(steps) = table["macro"].as_array() else {
+                return Err("macro must be a list of steps".to_string());
+            };
+            Ok(RemapAction::Macro(Macro {
+                steps: steps
+                    .iter()
+                    .map(parse_macro_step)
+                    .collect::<Result<Vec<MacroStep>, String>>()?,
+                repeat,
+            }))
+        }
+        toml::Value::Table(table) if table.contains_key("text") => {
+            check_fields(table, &["text"])?;
+            match &table["text"] {
+                toml::Value::String(text) => Ok(RemapAction::Macro(Macro {
+                    steps: vec![MacroStep::Text(text.clone())],
+                    repeat: 1,
+                })),
+                _ => Err("text must be a string".to_string()),
+            }
+        }
+        toml::Value::Table(table) if table.contains_key("layout") => {
+            check_fields(table, &["layout"])?;
+            match &table["layout"] {
+                toml::Value::String(layout) if layout == "previous" => {
+                    Ok(RemapAction::Layout(LayoutAction::Previous))
+                }
+                layout => Ok(RemapAction::Layout(LayoutAction::Switch(parse_layout_id(
+                    layout,
+                )?))),
+            }
+        }
+        toml::Value::Table(table) if table.contains_key("layer") => {
+            check_fields(table, &["layer"])?;
+            Ok(RemapAction::Layout(LayoutAction::Hold(parse_layout_id(
+                &table["layer"],
+            )?)))
+        }
+        toml::Value::Table(table) if table.contains_key("turbo") => {
+            check_fields(table, &["turbo", "rate"])?;
+            let rate = match table.get("rate") {
+                Some(toml::Value::Float(rate)) if *rate > 0.0 && *rate <= 1000.0 => Some(*rate),
+                Some(toml::Value::Integer(rate)) if (1..=1000).contains(rate) => Some(*rate as f64),
+                Some(_) => return Err("rate must be a number of presses per second between 0 and 1000".to_string()),
+                None => None,
+            };
+            Ok(RemapAction::Turbo(Turbo {
+                keys: parse_keys(&table["turbo"])?,
+                rate,
+            }))
+        }
+        toml::Value::Table(table) if table.contains_key("toggle_turbo") => {
+            check_fields(table, &["toggle_turbo"])?;
+            match table["toggle_turbo"].as_str().map(parse_event) {
+                Some(Ok(Event::Key(key))) => Ok(RemapAction::ToggleTurbo(Event::Key(key))),
+                Some(Ok(_)) => Err("toggle_turbo only works with keys and buttons".to_string()),
+                Some(Err(error)) => Err(error),
+                None => Err("toggle_turbo must be a key or button name".to_string()),
+            }
+        }
+        toml::Value::Table(table) if table.contains_key("toggle") => {
+            check_fields(table, &["toggle"])?;
+            Ok(RemapAction::Toggle(parse_keys(&table["toggle"])?))
+        }
+        toml::Value::Table(table) if table.contains_key("rumble") => {
+            check_fields(table, &["rumble", "strength"])?;
+            let duration = match table["rumble"] {
+                toml::Value::Integer(duration) if (1..=65535).contains(&duration) => duration as u16,
+                _ => return Err("rumble must be a duration in milliseconds between 1 and 65535".to_string()),
+            };
+            let strength = match table.get("strength") {
+                Some(toml::Value::Float(strength)) if (0.0..=1.0).contains(strength) => *strength,
+                Some(toml::Value::Integer(strength)) if (0..=1).contains(strength) => {
+                    *strength as f64
+                }
+                Some(_) => return Err("strength must be a number between 0.0 and 1.0".to_string()),
+                None => 1.0,
+            };
+            Ok(RemapAction::Rumble(Rumble { duration, strength }))
+        }
+        toml::Value::Table(_) => Err(
+            "unknown remap action, expected a list of keys, a tap-hold table, a macro, a text, a layout, a layer, a rumble, a turbo, a toggle or a gamepad target"
+                .to_string(),
+        ),
+        _ => Err("expected a list of keys, a gamepad target or a table".to_string()),
+    }
+}
+
+fn parse_macro_step(step: &toml::Value) -> Result<MacroStep, String> {
+    let step = match step.as_table() {
+        Some(table) if table.len() == 1 => table.iter().next().unwrap(),
+        _ => {
+            return Err(
+                "macro steps must be tables with one of press, release, tap, sleep or text, e.g. { tap = [\"KEY_A\"] }"
+                    .to_string(),
+            )
+        }
+    };
+    match (step.0.as_str(), step.1) {
+        ("press", keys) => Ok(MacroStep::Press(parse_keys(keys)?)),
+        ("release", keys) => Ok(MacroStep::Release(parse_keys(keys)?)),
+        ("tap", keys) => Ok(MacroStep::Tap(parse_keys(keys)?)),
+        ("sleep", toml::Value::Integer(ms)) if *ms >= 0 => Ok(MacroStep::Sleep(*ms as u64)),
+        ("sleep", _) => Err("sleep must be an integer >= 0".to_string()),
+        ("text", toml::Value::String(text)) => Ok(MacroStep::Text(text.clone())),
+        ("text", _) => Err("text must be a string".to_string()),
+        (step, _) => Err(format!("unknown macro step {:?}", step)),
+    }
+}
+
+fn parse_layout_id(layout: &toml::Value) -> Result<LayoutId, String> {
+    match layout {
+        toml::Value::Integer(layout) if (0..=u16::MAX as i64).contains(layout) => {
+            Ok(LayoutId::Number(*layout as u16))
+        }
+        toml::Value::String(layout) if validate_layout_name(layout) => {
+            Ok(LayoutId::Name(layout.clone()))
+        }
+        _ => Err("expected a layout number or a layout name".to_string()),
+    }
+}
+
+fn validate_layout_name(name: &str) -> bool {
+    !name.is_empty() && name.parse::<u16>().is_err() && name != "previous"
+}
+
+fn check_fields(table: &toml::Table, fields: &[&str]) -> Result<(), String> {
+    match table.keys().find(|field| !fields.contains(&field.as_str())) {
+        Some(field) => Err(format!("unknown field {:?}", field)),
+        None => Ok(()),
+    }
+}
+
+pub fn parse_keys(output: &toml::Value) -> Result<Vec<Key>, String> {
+    let Some(keys) = output.as_array() else {
+        return Err("expected a list of keys".to_string());
+    };
+    let mut event_list: Vec<Key> = Vec::new();
+    for key in keys {
+        match key.as_str().map(Key::from_str) {
+            Some(Ok(key)) => event_list.push(key),
+            Some(Err(_)) => return Err(format!("unknown key name {:?}", key.as_str().unwrap())),
+            None => return Err("expected a list of keys".to_string()),
+        }
+    }
+    Ok(event_list)
+}
+
+pub fn parse_commands(output: &toml::Value) -> Result<Vec<String>, String> {
+    match output.as_array() {
+        Some(commands) if commands.iter().all(|command| command.is_str()) => Ok(commands
+            .iter()
+            .map(|command| command.as_str().unwrap().to_string())
+            .collect()),
+        _ => Err("expected a list of commands".to_string()),
+    }
+}
+
+pub fn parse_movement(output: &toml::Value) -> Result<(Relative, bool), String> {
+    let (movement, toggle) = match output {
+        toml::Value::String(movement) => (movement, false),
+        toml::Value::Table(table) if table.contains_key("toggle") => {
+            check_fields(table, &["toggle"])?;
+            match &table["toggle"] {
+                toml::Value::String(movement) => (movement, true),
+                _ => return Err("toggle must be a movement".to_string()),
+            }
+        }
+        _ => return Err("expected a movement or a toggle table".to_string()),
+    };
+    let movement =
+        Relative::from_str(movement).map_err(|_| format!("unknown movement {:?}", movement))?;
+    Ok((movement, toggle))
+}
+
+pub enum SettingKind {
+    Bool,
+    Integer(i64, i64),
+    Float(f64, f64),
+    PositiveFloat(f64),
+    Modifiers,
+    Combination,
+    File,
+    LayoutName,
+    Curve,
+    Choice(&'static [&'static str]),
+}
+
+pub const SETTINGS: &[(&str, SettingKind)] = &[
+    ("GRAB_DEVICE", SettingKind::Bool),
+    (
+        "LSTICK",
+        SettingKind::Choice(&["cursor", "scroll", "bind", "disabled"]),
+    ),
+    (
+        "RSTICK",
+        SettingKind::Choice(&["cursor", "scroll", "bind", "disabled"]),
+    ),
+    ("LSTICK_SENSITIVITY", SettingKind::Integer(0, 1000)),
+    ("RSTICK_SENSITIVITY", SettingKind::Integer(0, 1000)),
+    ("LSTICK_DEADZONE", SettingKind::Integer(0, 128)),
+    ("RSTICK_DEADZONE", SettingKind::Integer(0, 128)),
+    ("LSTICK_OUTER_DEADZONE", SettingKind::Integer(0, 128)),
+    ("RSTICK_OUTER_DEADZONE", SettingKind::Integer(0, 128)),
+    (
+        "LSTICK_DEADZONE_MODE",
+        SettingKind::Choice(&["axial", "radial"]),
+    ),
+    (
+        "RSTICK_DEADZONE_MODE",
+        SettingKind::Choice(&["axial", "radial"]),
+    ),
+    ("LSTICK_CURVE", SettingKind::Curve),
+    ("RSTICK_CURVE", SettingKind::Curve),
+    ("LSTICK_ACTIVATION_MODIFIERS", SettingKind::Modifiers),
+    ("RSTICK_ACTIVATION_MODIFIERS", SettingKind::Modifiers),
+    ("INVERT_CURSOR_AXIS", SettingKind::Bool),
+    ("INVERT_SCROLL_AXIS", SettingKind::Bool),
+    ("CURSOR_SPEED", SettingKind::Integer(-1000, 1000)),
+    ("SCROLL_SPEED", SettingKind::Integer(-1000, 1000)),
+    ("CURSOR_ACCEL", SettingKind::Float(0.0, 1.0)),
+    ("SCROLL_ACCEL", SettingKind::Float(0.0, 1.0)),
+    ("16_BIT_AXIS", SettingKind::Bool),
+    ("CUSTOM_MODIFIERS", SettingKind::Modifiers),
+    ("STADIA", SettingKind::Bool),
+    ("CHAIN_ONLY", SettingKind::Bool),
+    ("LAYOUT_SWITCHER", SettingKind::Combination),
+    ("NOTIFY_LAYOUT_SWITCH", SettingKind::Bool),
+    ("RUMBLE_LAYOUT_SWITCH", SettingKind::Integer(0, 65535)),
+    (
+        "LAYOUT_LED",
+        SettingKind::Choice(&["num-lock", "caps-lock", "scroll-lock", "disabled"]),
+    ),
+    ("LAYOUT_NAME", SettingKind::LayoutName),
+    (
+        "REMEMBER_LAYOUT",
+        SettingKind::Choice(&["true", "false", "persistent"]),
+    ),
+    ("TAP_HOLD_TIMEOUT", SettingKind::Integer(1, 60000)),
+    ("TURBO_RATE", SettingKind::PositiveFloat(1000.0)),
+    ("STICKY_MODIFIERS", SettingKind::Modifiers),
+    ("STICKY_TIMEOUT", SettingKind::Integer(0, 3600000)),
+    ("CHORD_TIMEOUT", SettingKind::Integer(1, 1000)),
+    ("SEQUENCE_TIMEOUT", SettingKind::Integer(1, 60000)),
+    ("DOUBLE_TAP_TIMEOUT", SettingKind::Integer(1, 5000)),
+    ("LONG_PRESS_TIMEOUT", SettingKind::Integer(1, 60000)),
+    ("PERMISSIVE_HOLD", SettingKind::Bool),
+    ("XKB_KEYMAP", SettingKind::File),
+    (
+        "UNICODE_INPUT",
+        SettingKind::Choice(&["ctrl-shift-u", "disabled"]),
+    ),
+    (
+        "MOUSE_TO_STICK",
+        SettingKind::Choice(&["left", "right", "disabled"]),
+    ),
+    (
+        "MOUSE_STICK_SENSITIVITY",
+        SettingKind::PositiveFloat(1000.0),
+    ),
+    ("MOUSE_STICK_DEADZONE", SettingKind::Float(0.0, 1.0)),
+    ("MOUSE_STICK_CURVE", SettingKind::PositiveFloat(100.0)),
+    ("MOUSE_STICK_DECAY", SettingKind::Integer(0, 60000)),
+    ("GYRO", SettingKind::Choice(&["cursor", "disabled"])),
+    ("GYRO_SENSITIVITY", SettingKind::Float(-1000.0, 1000.0)),
+    ("GYRO_SMOOTHING", SettingKind::Integer(0, 60000)),
+    ("GYRO_ACTIVATION_MODIFIERS", SettingKind::Modifiers),
+    ("GYRO_RECALIBRATE", SettingKind::Combination),
+    (
+        "TOUCHPAD",
+        SettingKind::Choice(&["mouse", "gestures", "disabled"]),
+    ),
+    ("TOUCHPAD_SPEED", SettingKind::Float(-100.0, 100.0)),
+    ("TOUCHPAD_SCROLL_SPEED", SettingKind::Float(-100.0, 100.0)),
+    ("TOUCHPAD_NATURAL_SCROLL", SettingKind::Bool),
+    ("TOUCHPAD_TAP_TO_CLICK", SettingKind::Bool),
+];
+
+pub fn validate_setting(setting: &str, value: &str) -> Result<(), String> {
+    let Some((_, kind)) = SETTINGS.iter().find(|(name, _)| *name == setting) else {
+        return Err(format!("unknown setting {:?}", setting));
+    };
+    let valid = match kind {
+        SettingKind::Bool => value.parse::<bool>().is_ok(),
+        SettingKind::Integer(min, max) => value
+            .parse::<i64>()
+            .is_ok_and(|value| (*min..=*max).contains(&value)),
+        SettingKind::Float(min, max) => value
+            .parse::<f64>()
+            .is_ok_and(|value| (*min..=*max).contains(&value)),
+        SettingKind::PositiveFloat(max) => value
+            .parse::<f64>()
+            .is_ok_and(|value| value > 0.0 && value <= *max),
+        SettingKind::Modifiers => value
+            .split("-")
+            .all(|modifier| Key::from_str(modifier).is_ok() || Axis::from_str(modifier).is_ok()),
+        SettingKind::Combination => parse_combination(value).is_ok(),
+        SettingKind::File => std::path::Path::new(value).is_file(),
+        SettingKind::LayoutName => validate_layout_name(value),
+        SettingKind::Curve => Curve::from_str(value).is_ok(),
+        SettingKind::Choice(choices) => choices.contains(&value),
+    };
+    if valid {
+        return Ok(());
+    }
+    let expected = match kind {
+        SettingKind::Bool => "\"true\" or \"false\"".to_string(),
+        SettingKind::Integer(min, max) => format!("an integer between {} and {}", min, max),
+        SettingKind::Float(min, max) => format!("a number between {} and {}", min, max),
+        SettingKind::PositiveFloat(max) => format!("a number above 0 and up to {}", max),
+        SettingKind::Modifiers => "a list of keys separated by \"-\"".to_string(),
+        SettingKind::Combination => "a key or a combination of keys separated by \"-\"".to_string(),
+        SettingKind::File => "the path of an existing file".to_string(),
+        SettingKind::LayoutName => "a name that isn't a number or \"previous\"".to_string(),
+        SettingKind::Curve => {
+            "\"linear\", \"power:<exponent>\", \"exponential:<steepness>\" or a list of \"input,output\" points".to_string()
+        }
+        SettingKind::Choice(choices) => format!("one of {:?}", choices),
+    };
+    Err(format!(
+        "invalid value {:?} for {}, expected {}",
+        value, setting, expected
+    ))
+}
+
 pub fn parse_modifiers(settings: &HashMap<String, String>, parameter: &str) -> Vec<Event> {
     match settings.get(&parameter.to_string()) {
         Some(modifiers) => {
@@ -512,3 +1353,584 @@ pub fn parse_modifiers(settings: &HashMap<String, String>, parameter: &str) -> V
         None => Vec::new(),
     }
 }
+
+pub fn parse_combination(combination: &str) -> Result<(Event, Vec<Event>), String> {
+    let parse_key = |key: &str| match Key::from_str(key) {
+        Ok(key) => Ok(Event::Key(key)),
+        Err(_) => Err(format!("unknown key {:?}", key)),
+    };
+    if let Some(sequence) = combination.rsplit_once("-") {
+        let mut mods: Vec<Event> = sequence
+            .0
+            .split("-")
+            .map(parse_key)
+            .collect::<Result<Vec<Event>, String>>()?;
+        mods.sort();
+        mods.dedup();
+        Ok((parse_key(sequence.1)?, mods))
+    } else {
+        Ok((parse_key(combination)?, Vec::new()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn associations_default_to_layout_zero() {
+        assert_eq!(
+            parse_associations("Wireless Controller"),
+            Ok((Client::Default, 0))
+        );
+    }
+
+    #[test]
+    fn associations_read_numbers_as_layouts_and_names_as_window_classes() {
+        assert_eq!(
+            parse_associations("Wireless Controller::2"),
+            Ok((Client::Default, 2))
+        );
+        assert_eq!(
+            parse_associations("Wireless Controller::firefox"),
+            Ok((Client::Class("firefox".to_string()), 0))
+        );
+        assert_eq!(
+            parse_associations("Wireless Controller::gaming"),
+            Ok((Client::Class("gaming".to_string()), 0))
+        );
+    }
+
+    #[test]
+    fn associations_accept_layout_and_class_in_any_order() {
+        let expected = Ok((Client::Class("org.gnome.Nautilus".to_string()), 2));
+        assert_eq!(
+            parse_associations("Wireless Controller::2::org.gnome.Nautilus"),
+            expected
+        );
+        assert_eq!(
+            parse_associations("Wireless Controller::org.gnome.Nautilus::2"),
+            expected
+        );
+    }
+
+    #[test]
+    fn associations_reject_missing_layouts_and_extra_segments() {
+        assert!(parse_associations("Wireless Controller::firefox::gaming").is_err());
+        assert!(parse_associations("Wireless Controller::1::firefox::2").is_err());
+        assert!(parse_associations("Wireless Controller::firefox::70000").is_err());
+    }
+
+    #[test]
+    fn events_parse_keys_and_axes() {
+        assert_eq!(parse_event("KEY_A"), Ok(Event::Key(Key::KEY_A)));
+        assert_eq!(
+            parse_event("BTN_DPAD_UP"),
+            Ok(Event::Axis(Axis::BTN_DPAD_UP))
+        );
+        assert!(parse_event("KEY_NOPE").is_err());
+        assert!(parse_event("").is_err());
+    }
+
+    #[test]
+    fn bindings_parse_modifiers_and_hold() {
+        assert_eq!(
+            parse_binding("KEY_LEFTSHIFT-KEY_LEFTCTRL-KEY_LEFTSHIFT-KEY_A"),
+            Ok((
+                Event::Key(Key::KEY_A),
+                vec![
+                    Event::Key(Key::KEY_LEFTCTRL),
+                    Event::Key(Key::KEY_LEFTSHIFT)
+                ]
+            ))
+        );
+        assert_eq!(
+            parse_binding("-KEY_A"),
+            Ok((Event::Key(Key::KEY_A), vec![Event::Hold]))
+        );
+        assert!(parse_binding("KEY_NOPE-KEY_A").is_err());
+    }
+
+    #[test]
+    fn bindings_accept_timed_suffixes_on_the_trigger() {
+        assert_eq!(
+            parse_binding("BTN_MODE_DOUBLE_TAP"),
+            Ok((Event::DoubleTap(Key::BTN_MODE), Vec::new()))
+        );
+        assert_eq!(
+            parse_binding("KEY_LEFTCTRL-KEY_A_LONG_PRESS"),
+            Ok((
+                Event::LongPress(Key::KEY_A),
+                vec![Event::Key(Key::KEY_LEFTCTRL)]
+            ))
+        );
+        assert!(parse_binding("KEY_NOPE_DOUBLE_TAP").is_err());
+    }
+
+    #[test]
+    fn chords_are_sorted_and_deduplicated() {
+        assert_eq!(
+            parse_chord("KEY_K+KEY_J"),
+            Ok((
+                vec![Event::Key(Key::KEY_J), Event::Key(Key::KEY_K)],
+                Vec::new()
+            ))
+        );
+        assert_eq!(
+            parse_chord("KEY_LEFTCTRL-KEY_J+KEY_K"),
+            Ok((
+                vec![Event::Key(Key::KEY_J), Event::Key(Key::KEY_K)],
+                vec![Event::Key(Key::KEY_LEFTCTRL)]
+            ))
+        );
+    }
+
+    #[test]
+    fn chords_need_two_different_keys() {
+        assert!(parse_chord("KEY_J+KEY_J").is_err());
+        assert!(parse_chord("KEY_J+").is_err());
+        assert!(parse_chord("KEY_J+KEY_NOPE").is_err());
+    }
+
+    #[test]
+    fn chords_can_only_be_remapped_to_keys() {
+        assert_eq!(
+            parse_chord_remap(&toml::Value::Array(vec!["KEY_ESC".into()])).unwrap(),
+            vec![Key::KEY_ESC]
+        );
+        assert!(parse_chord_remap(&toml::Value::String("BTN_A".to_string())).is_err());
+    }
+
+    #[test]
+    fn sequences_need_at_least_two_keys() {
+        assert_eq!(
+            parse_sequence("KEY_SPACE  KEY_F"),
+            Ok(vec![Event::Key(Key::KEY_SPACE), Event::Key(Key::KEY_F)])
+        );
+        assert!(parse_sequence("KEY_SPACE").is_err());
+        assert!(parse_sequence("KEY_SPACE KEY_NOPE").is_err());
+    }
+
+    #[test]
+    fn sequences_only_contain_keys() {
+        assert!(parse_sequence("BTN_DPAD_UP BTN_DPAD_DOWN").is_err());
+    }
+
+    #[test]
+    fn settings_accept_values_in_range() {
+        assert_eq!(validate_setting("CHAIN_ONLY", "false"), Ok(()));
+        assert_eq!(validate_setting("LSTICK_SENSITIVITY", "0"), Ok(()));
+        assert_eq!(validate_setting("LSTICK_DEADZONE", "128"), Ok(()));
+        assert_eq!(validate_setting("CURSOR_SPEED", "-1000"), Ok(()));
+        assert_eq!(validate_setting("MOUSE_STICK_DEADZONE", "0.25"), Ok(()));
+        assert_eq!(
+            validate_setting("CUSTOM_MODIFIERS", "KEY_CAPSLOCK-BTN_DPAD_UP"),
+            Ok(())
+        );
+        assert_eq!(
+            validate_setting("LAYOUT_SWITCHER", "KEY_LEFTCTRL-KEY_F12"),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn settings_reject_values_that_used_to_panic() {
+        assert!(validate_setting("CHAIN_ONLY", "yes").is_err());
+        assert!(validate_setting("LSTICK_SENSITIVITY", "-1").is_err());
+        assert!(validate_setting("LSTICK_SENSITIVITY", "fast").is_err());
+        assert!(validate_setting("LSTICK_DEADZONE", "129").is_err());
+        assert!(validate_setting("TAP_HOLD_TIMEOUT", "0").is_err());
+        assert!(validate_setting("MOUSE_STICK_DEADZONE", "1.5").is_err());
+        assert!(validate_setting("MOUSE_STICK_DEADZONE", "NaN").is_err());
+        assert!(validate_setting("CUSTOM_MODIFIERS", "KEY_CAPSLOCK-KEY_NOPE").is_err());
+        assert!(validate_setting("LAYOUT_SWITCHER", "KEY_LEFTCTRL-").is_err());
+    }
+
+    #[test]
+    fn unknown_settings_are_reported_as_such() {
+        assert!(validate_setting("NOT_A_SETTING", "1")
+            .unwrap_err()
+            .starts_with("unknown setting"));
+    }
+
+    #[test]
+    fn combinations_report_invalid_keys() {
+        assert_eq!(
+            parse_combination("KEY_LEFTSHIFT-KEY_LEFTCTRL-KEY_A"),
+            Ok((
+                Event::Key(Key::KEY_A),
+                vec![
+                    Event::Key(Key::KEY_LEFTCTRL),
+                    Event::Key(Key::KEY_LEFTSHIFT)
+                ]
+            ))
+        );
+        assert!(parse_combination("KEY_NOPE").is_err());
+        assert!(parse_combination("KEY_LEFTCTRL-KEY_NOPE").is_err());
+        assert!(parse_combination("KEY_LEFTCTRL-LSTICK_UP").is_err());
+    }
+
+    fn remap(output: &str) -> Result<RemapAction, String> {
+        parse_remap(
+            &toml::from_str::<toml::Table>(&format!("output = {}", output)).unwrap()["output"],
+        )
+    }
+
+    #[test]
+    fn tap_holds_parse_their_options() {
+        let Ok(RemapAction::TapHold(tap_hold)) = remap(
+            r#"{ tap = ["KEY_ESC"], hold = ["KEY_LEFTCTRL"], timeout = 300, permissive_hold = true }"#,
+        ) else {
+            panic!("expected a tap-hold binding");
+        };
+        assert_eq!(tap_hold.tap, vec![Key::KEY_ESC]);
+        assert_eq!(tap_hold.hold, vec![Key::KEY_LEFTCTRL]);
+        assert_eq!(tap_hold.timeout, Some(300));
+        assert_eq!(tap_hold.permissive_hold, Some(true));
+        let Ok(RemapAction::TapHold(tap_hold)) =
+            remap(r#"{ tap = ["KEY_ESC"], hold = ["KEY_LEFTCTRL"] }"#)
+        else {
+            panic!("expected a tap-hold binding");
+        };
+        assert_eq!((tap_hold.timeout, tap_hold.permissive_hold), (None, None));
+    }
+
+    #[test]
+    fn tap_holds_reject_invalid_options() {
+        assert!(remap(r#"{ tap = ["KEY_ESC"] }"#).is_err());
+        assert!(remap(r#"{ tap = ["KEY_ESC"], hold = ["KEY_NOPE"] }"#).is_err());
+        assert!(remap(r#"{ tap = ["KEY_ESC"], hold = ["KEY_LEFTCTRL"], timeout = -1 }"#).is_err());
+        assert!(remap(
+            r#"{ tap = ["KEY_ESC"], hold = ["KEY_LEFTCTRL"], permissive_hold = "yes" }"#
+        )
+        .is_err());
+        assert!(remap(r#"{ tap = ["KEY_ESC"], hold = ["KEY_LEFTCTRL"], delay = 5 }"#).is_err());
+    }
+
+    #[test]
+    fn layout_actions_accept_numbers_names_and_previous() {
+        assert!(matches!(
+            remap("{ layout = 2 }"),
+            Ok(RemapAction::Layout(LayoutAction::Switch(LayoutId::Number(
+                2
+            ))))
+        ));
+        assert!(matches!(
+            remap(r#"{ layout = "gaming" }"#),
+            Ok(RemapAction::Layout(LayoutAction::Switch(LayoutId::Name(name)))) if name == "gaming"
+        ));
+        assert!(matches!(
+            remap(r#"{ layout = "previous" }"#),
+            Ok(RemapAction::Layout(LayoutAction::Previous))
+        ));
+    }
+
+    #[test]
+    fn layers_accept_numbers_and_names() {
+        assert!(matches!(
+            remap("{ layer = 1 }"),
+            Ok(RemapAction::Layout(LayoutAction::Hold(LayoutId::Number(1))))
+        ));
+        assert!(matches!(
+            remap(r#"{ layer = "symbols" }"#),
+            Ok(RemapAction::Layout(LayoutAction::Hold(LayoutId::Name(name)))) if name == "symbols"
+        ));
+    }
+
+    #[test]
+    fn layout_actions_reject_invalid_layouts() {
+        assert!(remap("{ layout = -1 }").is_err());
+        assert!(remap("{ layout = 65536 }").is_err());
+        assert!(remap(r#"{ layout = "" }"#).is_err());
+        assert!(remap(r#"{ layer = "previous" }"#).is_err());
+        assert!(remap(r#"{ layer = "3" }"#).is_err());
+        assert!(remap("{ layer = 1, layout = 2 }").is_err());
+    }
+
+    #[test]
+    fn gamepad_targets_cover_sticks_triggers_and_buttons() {
+        assert_eq!(
+            GamepadTarget::from_str("LSTICK_LEFT"),
+            Ok(GamepadTarget::Axis(AbsoluteAxisType::ABS_X, -1))
+        );
+        assert_eq!(
+            GamepadTarget::from_str("BTN_DPAD_DOWN"),
+            Ok(GamepadTarget::Axis(AbsoluteAxisType::ABS_HAT0Y, 1))
+        );
+        assert_eq!(
+            GamepadTarget::from_str("ABS_RZ"),
+            Ok(GamepadTarget::Trigger(AbsoluteAxisType::ABS_RZ))
+        );
+        assert_eq!(
+            GamepadTarget::from_str("BTN_A"),
+            Ok(GamepadTarget::Button(Key::BTN_SOUTH))
+        );
+        assert_eq!(
+            GamepadTarget::from_str("BTN_START"),
+            Ok(GamepadTarget::Button(Key::BTN_START))
+        );
+        assert!(GamepadTarget::from_str("KEY_A").is_err());
+        assert!(GamepadTarget::from_str("LSTICK").is_err());
+    }
+
+    #[test]
+    fn gamepad_outputs_parse_their_magnitude() {
+        let Ok(RemapAction::Gamepad(output)) = remap(r#""RSTICK_UP""#) else {
+            panic!("expected a gamepad output");
+        };
+        assert_eq!(
+            output.target,
+            GamepadTarget::Axis(AbsoluteAxisType::ABS_RY, -1)
+        );
+        assert_eq!(output.magnitude, 1.0);
+        let Ok(RemapAction::Gamepad(output)) =
+            remap(r#"{ gamepad = "LSTICK_UP", magnitude = 0.5 }"#)
+        else {
+            panic!("expected a gamepad output");
+        };
+        assert_eq!(output.magnitude, 0.5);
+        assert!(remap(r#"{ gamepad = "LSTICK_UP", magnitude = 1.5 }"#).is_err());
+        assert!(remap(r#"{ gamepad = "LSTICK_UP", magnitude = -1 }"#).is_err());
+        assert!(remap(r#"{ gamepad = "KEY_A" }"#).is_err());
+        assert!(remap(r#""KEY_A""#).is_err());
+    }
+
+    #[test]
+    fn turbo_rates_must_be_positive() {
+        let Ok(RemapAction::Turbo(turbo)) = remap(r#"{ turbo = ["BTN_SOUTH"], rate = 12.5 }"#)
+        else {
+            panic!("expected a turbo binding");
+        };
+        assert_eq!(turbo.keys, vec![Key::BTN_SOUTH]);
+        assert_eq!(turbo.rate, Some(12.5));
+        let Ok(RemapAction::Turbo(turbo)) = remap(r#"{ turbo = ["BTN_SOUTH"] }"#) else {
+            panic!("expected a turbo binding");
+        };
+        assert_eq!(turbo.rate, None);
+        assert!(remap(r#"{ turbo = ["BTN_SOUTH"], rate = 0 }"#).is_err());
+        assert!(remap(r#"{ turbo = ["BTN_SOUTH"], rate = -2.0 }"#).is_err());
+        assert!(remap(r#"{ turbo = ["BTN_SOUTH"], rate = nan }"#).is_err());
+        assert!(remap(r#"{ turbo = ["BTN_SOUTH"], rate = 1001 }"#).is_err());
+    }
+
+    #[test]
+    fn turbo_rate_setting_must_be_positive() {
+        assert_eq!(validate_setting("TURBO_RATE", "0.5"), Ok(()));
+        assert!(validate_setting("TURBO_RATE", "0").is_err());
+        assert!(validate_setting("TURBO_RATE", "-1").is_err());
+        assert!(validate_setting("TURBO_RATE", "NaN").is_err());
+        assert!(validate_setting("TURBO_RATE", "inf").is_err());
+    }
+
+    #[test]
+    fn turbo_toggles_only_work_with_keys() {
+        assert!(matches!(
+            remap(r#"{ toggle_turbo = "BTN_SOUTH" }"#),
+            Ok(RemapAction::ToggleTurbo(Event::Key(Key::BTN_SOUTH)))
+        ));
+        assert!(remap(r#"{ toggle_turbo = "BTN_DPAD_UP" }"#).is_err());
+        assert!(remap(r#"{ toggle_turbo = 1 }"#).is_err());
+    }
+
+    #[test]
+    fn macros_parse_their_steps_and_repeat() {
+        let Ok(RemapAction::Macro(binding)) = remap(
+            r#"{ macro = [{ press = ["KEY_LEFTCTRL"] }, { tap = ["KEY_C"] }, { release = ["KEY_LEFTCTRL"] }, { sleep = 50 }, { text = "hi" }], repeat = 0 }"#,
+        ) else {
+            panic!("expected a macro");
+        };
+        assert_eq!(binding.repeat, 0);
+        assert!(matches!(
+            binding.steps.as_slice(),
+            [
+                MacroStep::Press(press),
+                MacroStep::Tap(tap),
+                MacroStep::Release(release),
+                MacroStep::Sleep(50),
+                MacroStep::Text(text),
+            ] if press == &vec![Key::KEY_LEFTCTRL]
+                && tap == &vec![Key::KEY_C]
+                && release == &vec![Key::KEY_LEFTCTRL]
+                && text == "hi"
+        ));
+        let Ok(RemapAction::Macro(binding)) = remap(r#"{ macro = [{ tap = ["KEY_A"] }] }"#) else {
+            panic!("expected a macro");
+        };
+        assert_eq!(binding.repeat, 1);
+        let Ok(RemapAction::Macro(binding)) = remap(r#"{ text = "Hello" }"#) else {
+            panic!("expected a text macro");
+        };
+        assert_eq!(binding.repeat, 1);
+        assert!(matches!(binding.steps.as_slice(), [MacroStep::Text(text)] if text == "Hello"));
+    }
+
+    #[test]
+    fn macros_reject_invalid_steps() {
+        assert!(remap(r#"{ macro = [{ tap = ["KEY_A"] }], repeat = -1 }"#).is_err());
+        assert!(remap(r#"{ macro = [{ tap = ["KEY_A"] }], repeat = "forever" }"#).is_err());
+        assert!(remap(r#"{ macro = [{ tap = ["KEY_A"] }], rate = 2 }"#).is_err());
+        assert!(remap(r#"{ macro = { tap = ["KEY_A"] } }"#).is_err());
+        assert!(remap(r#"{ macro = [{ tap = ["KEY_A"], sleep = 5 }] }"#).is_err());
+        assert!(remap(r#"{ macro = [{ tap = ["KEY_NOPE"] }] }"#).is_err());
+        assert!(remap(r#"{ macro = [{ sleep = -5 }] }"#).is_err());
+        assert!(remap(r#"{ macro = [{ text = 5 }] }"#).is_err());
+        assert!(remap(r#"{ macro = [{ hold = ["KEY_A"] }] }"#).is_err());
+        assert!(remap(r#"{ text = ["KEY_A"] }"#).is_err());
+    }
+
+    #[test]
+    fn toggles_accept_keys_and_movements() {
+        let Ok(RemapAction::Toggle(keys)) = remap(r#"{ toggle = ["KEY_LEFTSHIFT", "KEY_W"] }"#)
+        else {
+            panic!("expected a toggle binding");
+        };
+        assert_eq!(keys, vec![Key::KEY_LEFTSHIFT, Key::KEY_W]);
+        assert!(remap(r#"{ toggle = ["KEY_NOPE"] }"#).is_err());
+        assert!(remap(r#"{ toggle = ["KEY_W"], rate = 2 }"#).is_err());
+        let movement = |output: &str| {
+            parse_movement(
+                &toml::from_str::<toml::Table>(&format!("output = {}", output)).unwrap()["output"],
+            )
+        };
+        assert!(matches!(
+            movement(r#"{ toggle = "CURSOR_UP" }"#),
+            Ok((Relative::Cursor(Cursor::CURSOR_UP), true))
+        ));
+        assert!(matches!(
+            movement(r#""SCROLL_DOWN""#),
+            Ok((Relative::Scroll(Scroll::SCROLL_DOWN), false))
+        ));
+        assert!(movement(r#"{ toggle = ["CURSOR_UP"] }"#).is_err());
+        assert!(movement(r#"{ toggle = "CURSOR_NOPE" }"#).is_err());
+    }
+
+    #[test]
+    fn device_fields_accept_hex_ids_and_bus_names() {
+        let mut device_match: DeviceMatch = Default::default();
+        device_match
+            .set_field("vendor", &toml::Value::String("054c".to_string()))
+            .unwrap();
+        device_match
+            .set_field("product", &toml::Value::String("0x0ce6".to_string()))
+            .unwrap();
+        device_match
+            .set_field("bustype", &toml::Value::String("bluetooth".to_string()))
+            .unwrap();
+        assert_eq!(device_match.vendor, Some(0x054c));
+        assert_eq!(device_match.product, Some(0x0ce6));
+        assert_eq!(device_match.bustype, Some(0x05));
+        device_match
+            .set_field("vendor", &toml::Value::Integer(0x045e))
+            .unwrap();
+        device_match
+            .set_field("bustype", &toml::Value::Integer(3))
+            .unwrap();
+        assert_eq!(device_match.vendor, Some(0x045e));
+        assert_eq!(device_match.bustype, Some(0x03));
+    }
+
+    #[test]
+    fn device_fields_accept_strings_and_regular_expressions() {
+        let mut device_match: DeviceMatch = Default::default();
+        device_match
+            .set_field(
+                "phys",
+                &toml::Value::String("usb-0000:00:14.0-2/input0".into()),
+            )
+            .unwrap();
+        device_match
+            .set_field("uniq", &toml::Value::String("a0:5a:5c:11:22:33".into()))
+            .unwrap();
+        device_match
+            .set_field("name", &toml::Value::String("^Wireless Controller$".into()))
+            .unwrap();
+        assert_eq!(
+            device_match.phys.as_deref(),
+            Some("usb-0000:00:14.0-2/input0")
+        );
+        assert_eq!(device_match.uniq.as_deref(), Some("a0:5a:5c:11:22:33"));
+        let name = device_match.name.unwrap();
+        assert!(name.is_match("Wireless Controller"));
+        assert!(!name.is_match("Wireless Controller Touchpad"));
+    }
+
+    #[test]
+    fn device_fields_reject_invalid_values() {
+        let mut device_match: DeviceMatch = Default::default();
+        for (field, value) in [
+            ("vendor", toml::Value::Integer(-1)),
+            ("vendor", toml::Value::Integer(0x10000)),
+            ("product", toml::Value::String("zzzz".into())),
+            ("product", toml::Value::Boolean(true)),
+            ("bustype", toml::Value::String("serial".into())),
+            ("phys", toml::Value::Integer(1)),
+            ("name", toml::Value::String("(unclosed".into())),
+            ("serial", toml::Value::String("1234".into())),
+        ] {
+            assert!(device_match.set_field(field, &value).is_err(), "{}", field);
+        }
+        assert!(DeviceMatch::new(&HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn curves_parse_presets_and_points() {
+        assert_eq!(Curve::from_str("linear"), Ok(Curve::Linear));
+        assert_eq!(Curve::from_str("power:2.5"), Ok(Curve::Power(2.5)));
+        assert_eq!(
+            Curve::from_str(" exponential: 3 "),
+            Ok(Curve::Exponential(3.0))
+        );
+        assert_eq!(
+            Curve::from_str("0.25,0.1 0.75,0.5"),
+            Ok(Curve::Points(vec![(0.25, 0.1), (0.75, 0.5)]))
+        );
+    }
+
+    #[test]
+    fn curves_reject_invalid_values() {
+        for curve in [
+            "",
+            "cubic",
+            "power:0",
+            "power:-1",
+            "power:NaN",
+            "exponential:fast",
+            "linear:2",
+            "0.5",
+            "0,0.5",
+            "0.5,0.1 0.5,0.2",
+            "0.75,0.5 0.25,0.1",
+            "1.5,0.5",
+            "0.5,1.5",
+        ] {
+            assert!(Curve::from_str(curve).is_err(), "{:?}", curve);
+        }
+    }
+
+    #[test]
+    fn curves_map_the_unit_range_onto_itself() {
+        for curve in ["linear", "power:2", "exponential:3", "0.5,0.2"] {
+            let curve = Curve::from_str(curve).unwrap();
+            assert_eq!(curve.apply(0.0), 0.0);
+            assert!((curve.apply(1.0) - 1.0).abs() < 1e-9);
+            assert_eq!(curve.apply(2.0), curve.apply(1.0));
+        }
+        let steepest = Curve::from_str(&format!("exponential:{}", MAX_CURVE_STEEPNESS)).unwrap();
+        assert_eq!(steepest.apply(1.0), 1.0);
+        assert!(steepest.apply(0.5).is_finite());
+        assert!(Curve::from_str("exponential:50.5").is_err());
+        assert!(Curve::from_str("exponential:1000").is_err());
+        assert_eq!(Curve::Power(2.0).apply(0.5), 0.25);
+        let points = Curve::from_str("0.5,0.2").unwrap();
+        assert!((points.apply(0.25) - 0.1).abs() < 1e-9);
+        assert!((points.apply(0.75) - 0.6).abs() < 1e-9);
+    }
+
+    #[test]
+    fn timed_suffixes_are_rejected_outside_of_the_trigger() {
+        assert!(parse_event("KEY_A_DOUBLE_TAP").is_err());
+        assert!(parse_binding("KEY_LEFTCTRL_LONG_PRESS-KEY_A").is_err());
+        assert!(parse_chord("KEY_A_DOUBLE_TAP+KEY_B").is_err());
+        assert!(parse_sequence("KEY_A KEY_B_LONG_PRESS").is_err());
+    }
+}
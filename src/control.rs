@@ -0,0 +1,140 @@
+use crate::config::LayoutId;
+use crate::socket::get_socket_path;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::UnixListener;
+use tokio::sync::{mpsc, oneshot};
+
+pub enum ControlRequest {
+    List,
+    Layout(LayoutId, Option<String>),
+    Reload,
+    Pause(Option<String>),
+    Resume(Option<String>),
+}
+
+pub type ControlMessage = (ControlRequest, oneshot::Sender<String>);
+
+pub fn start_control_server() -> Option<mpsc::Receiver<ControlMessage>> {
+    let socket_path = get_socket_path();
+    let _ = std::fs::remove_file(&socket_path);
+    let listener = match UnixListener::bind(&socket_path) {
+        Ok(listener) => listener,
+        Err(error) => {
+            println!(
+                "Warning: unable to create the control socket {:?}: {}, makimactl won't be available.\n",
+                socket_path, error
+            );
+            return None;
+        }
+    };
+    let (sender, receiver) = mpsc::channel::<ControlMessage>(8);
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let sender = sender.clone();
+            tokio::spawn(async move {
+                let (reader, mut writer) = stream.into_split();
+                let mut line = String::new();
+                if BufReader::new(reader).read_line(&mut line).await.is_err() {
+                    return;
+                }
+                let reply = match parse_request(&line) {
+                    Ok(request) => {
+                        let (reply_sender, reply_receiver) = oneshot::channel();
+                        if sender.send((request, reply_sender)).await.is_err() {
+                            return;
+                        }
+                        reply_receiver.await.unwrap_or_default()
+                    }
+                    Err(error) => format!("Error: {}\n", error),
+                };
+                let _ = writer.write_all(reply.as_bytes()).await;
+            });
+        }
+    });
+    Some(receiver)
+}
+
+fn parse_request(line: &str) -> Result<ControlRequest, String> {
+    let line = line.trim();
+    let (command, argument) = line.split_once(' ').unwrap_or((line, ""));
+    let device = match argument.trim() {
+        "" => None,
+        device => Some(device.to_string()),
+    };
+    match command {
+        "list" if device.is_none() => Ok(ControlRequest::List),
+        "reload" if device.is_none() => Ok(ControlRequest::Reload),
+        "layout" => {
+            let argument = argument.trim();
+            let (layout, device) = argument.split_once(' ').unwrap_or((argument, ""));
+            match (layout, device.trim()) {
+                ("", _) => Err("missing layout".to_string()),
+                (layout, "") => Ok(ControlRequest::Layout(LayoutId::new(layout), None)),
+                (layout, device) => Ok(ControlRequest::Layout(
+                    LayoutId::new(layout),
+                    Some(device.to_string()),
+                )),
+            }
+        }
+        "pause" => Ok(ControlRequest::Pause(device)),
+        "resume" => Ok(ControlRequest::Resume(device)),
+        _ => Err(format!("unknown request {:?}", line)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests_without_arguments() {
+        assert!(matches!(parse_request("list\n"), Ok(ControlRequest::List)));
+        assert!(matches!(
+            parse_request(" reload "),
+            Ok(ControlRequest::Reload)
+        ));
+        assert!(matches!(
+            parse_request("pause"),
+            Ok(ControlRequest::Pause(None))
+        ));
+        assert!(matches!(
+            parse_request("resume"),
+            Ok(ControlRequest::Resume(None))
+        ));
+    }
+
+    #[test]
+    fn requests_with_a_device() {
+        assert!(matches!(
+            parse_request("pause event5"),
+            Ok(ControlRequest::Pause(Some(device))) if device == "event5"
+        ));
+        assert!(matches!(
+            parse_request("resume Wireless Controller"),
+            Ok(ControlRequest::Resume(Some(device))) if device == "Wireless Controller"
+        ));
+    }
+
+    #[test]
+    fn layout_requests_take_a_number_or_a_name_and_an_optional_device() {
+        assert!(matches!(
+            parse_request("layout 2"),
+            Ok(ControlRequest::Layout(LayoutId::Number(2), None))
+        ));
+        assert!(matches!(
+            parse_request("layout gaming Wireless Controller"),
+            Ok(ControlRequest::Layout(LayoutId::Name(name), Some(device)))
+                if name == "gaming" && device == "Wireless Controller"
+        ));
+    }
+
+    #[test]
+    fn invalid_requests_are_rejected() {
+        assert!(parse_request("").is_err());
+        assert!(parse_request("layout").is_err());
+        assert!(parse_request("layout  ").is_err());
+        assert!(parse_request("list event5").is_err());
+        assert!(parse_request("reload now").is_err());
+        assert!(parse_request("restart").is_err());
+    }
+}
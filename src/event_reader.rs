@@ -1,25 +1,49 @@
 use crate::active_client::*;
-use crate::config::{parse_modifiers, Associations, Axis, Cursor, Event, Relative, Scroll};
-use crate::udev_monitor::Environment;
-use crate::virtual_devices::VirtualDevices;
+use crate::config::{
+    parse_combination, parse_modifiers, validate_setting, Associations, Axis, Cursor, Curve, Event,
+    LayoutAction, LayoutId, Macro, MacroStep, Relative, Scroll, TapHold, Toggle,
+};
+use crate::keymap::TextKeymap;
+use crate::touchpad::{Touchpad, TouchpadAction, TouchpadSettings};
+use crate::udev_monitor::{Client, Environment, SharedState};
+use crate::virtual_devices::{KeyboardLeds, VirtualDevices, VirtualGamepad};
 use crate::Config;
-use evdev::{AbsoluteAxisType, EventStream, EventType, InputEvent, Key, RelativeAxisType};
+use evdev::{
+    AbsoluteAxisType, EventStream, EventType, FFEffectData, FFEffectKind, FFReplay, FFTrigger,
+    InputEvent, Key, LedType, RelativeAxisType,
+};
 use fork::{fork, setsid, Fork};
 use std::{
+    collections::{BTreeMap, HashMap},
     future::Future,
     option::Option,
+    path::Path,
     pin::Pin,
     process::{Command, Stdio},
     str::FromStr,
-    sync::Arc,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, OnceLock,
+    },
 };
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, Notify};
+use tokio::task::JoinHandle;
+use tokio::time::{Duration, Instant};
 use tokio_stream::StreamExt;
 
+// Saves run on blocking threads and can finish out of order, so each one carries the
+// version it was taken at and the lock keeps the last version written for every device.
+static LAYOUT_STATE_LOCK: std::sync::Mutex<BTreeMap<String, u64>> =
+    std::sync::Mutex::new(BTreeMap::new());
+static LAYOUT_MEMORY_VERSION: AtomicU64 = AtomicU64::new(0);
+
 struct Stick {
     function: String,
     sensitivity: u64,
     deadzone: i32,
+    outer_deadzone: i32,
+    radial_deadzone: bool,
+    curve: Curve,
     activation_modifiers: Vec<Event>,
 }
 
@@ -28,6 +52,181 @@ struct Movement {
     acceleration: f32,
 }
 
+struct Gyro {
+    sensitivity: f64,
+    smoothing: u64,
+    activation_modifiers: Vec<Event>,
+    recalibrate: Option<(Event, Vec<Event>)>,
+}
+
+struct MouseStick {
+    axes: (AbsoluteAxisType, AbsoluteAxisType),
+    sensitivity: f64,
+    deadzone: f64,
+    curve: f64,
+    decay: u64,
+}
+
+type BufferedEvent = (InputEvent, Event, i32, bool);
+
+struct TapHoldState {
+    event: Event,
+    binding: TapHold,
+    deadline: Instant,
+    permissive_hold: bool,
+    held: bool,
+}
+
+impl TapHoldState {
+    fn new(
+        event: Event,
+        binding: &TapHold,
+        default_timeout: u64,
+        default_permissive_hold: bool,
+        now: Instant,
+    ) -> Self {
+        Self {
+            event,
+            binding: binding.clone(),
+            deadline: now + Duration::from_millis(binding.timeout.unwrap_or(default_timeout)),
+            permissive_hold: binding.permissive_hold.unwrap_or(default_permissive_hold),
+            held: false,
+        }
+    }
+
+    fn expire(&mut self, now: Instant) -> bool {
+        let expired = !self.held && self.deadline <= now;
+        self.held |= expired;
+        expired
+    }
+
+    fn interrupt(&mut self) -> bool {
+        let interrupted = !self.held && self.permissive_hold;
+        self.held |= interrupted;
+        interrupted
+    }
+}
+
+struct HeldLayer {
+    event: Event,
+    previous_layout: u16,
+    pressed: Vec<(InputEvent, Event)>,
+}
+
+struct PendingChord {
+    events: Vec<(InputEvent, Event, bool)>,
+    modifiers: Vec<Event>,
+    deadline: Instant,
+}
+
+struct ActiveChord {
+    keys: Vec<Event>,
+    output: Vec<Key>,
+    released: bool,
+}
+
+pub struct StickyModifier {
+    key: Key,
+    virt_dev: Arc<Mutex<VirtualDevices>>,
+    state: StickyState,
+}
+
+struct StickyState {
+    held: bool,
+    used: bool,
+    locked: bool,
+    unlocking: bool,
+    lock_until: Option<Instant>,
+    deadline: Option<Instant>,
+}
+
+impl StickyState {
+    fn new() -> Self {
+        Self {
+            held: true,
+            used: false,
+            locked: false,
+            unlocking: false,
+            lock_until: None,
+            deadline: None,
+        }
+    }
+
+    fn press(&mut self, now: Instant) {
+        self.held = true;
+        self.used = false;
+        self.deadline = None;
+        if !self.locked && self.lock_until.is_some_and(|lock_until| lock_until > now) {
+            self.locked = true;
+        } else {
+            self.unlocking = true;
+        }
+        self.lock_until = None;
+    }
+
+    fn release(&mut self, now: Instant, double_tap_timeout: u64, sticky_timeout: u64) -> bool {
+        self.held = false;
+        if self.used || self.unlocking {
+            return false;
+        }
+        if !self.locked {
+            self.lock_until = Some(now + Duration::from_millis(double_tap_timeout));
+            self.deadline =
+                (sticky_timeout > 0).then(|| now + Duration::from_millis(sticky_timeout));
+        }
+        true
+    }
+
+    fn consume(&mut self) -> bool {
+        if self.held {
+            self.used = true;
+            return true;
+        }
+        self.locked
+    }
+
+    fn expire(&self, now: Instant) -> bool {
+        self.deadline.is_some_and(|deadline| deadline <= now)
+    }
+}
+
+struct TurboState {
+    event: Event,
+    keys: Vec<Key>,
+    interval: Duration,
+    next: Instant,
+    pressed: bool,
+}
+
+struct PendingSequence {
+    events: Vec<(InputEvent, Event, i32, bool)>,
+    held: Vec<Event>,
+    deadline: Instant,
+}
+
+#[derive(Debug, PartialEq)]
+enum SequenceMatch {
+    Prefix,
+    Complete(u16),
+    NoMatch,
+}
+
+struct TimedKeyState {
+    key: Key,
+    default_event: InputEvent,
+    send_zero: bool,
+    pressed: bool,
+    double_tap: bool,
+    long_press: bool,
+    deadline: Option<Instant>,
+    fired: Option<Event>,
+}
+
+struct MacroTask {
+    task: JoinHandle<()>,
+    held_keys: Arc<Mutex<Vec<Key>>>,
+}
+
 struct Settings {
     lstick: Stick,
     rstick: Stick,
@@ -40,22 +239,73 @@ struct Settings {
     chain_only: bool,
     layout_switcher: Option<(Event, Vec<Event>)>,
     notify_layout_switch: bool,
+    rumble_layout_switch: u16,
+    layout_led: Option<LedType>,
+    tap_hold_timeout: u64,
+    permissive_hold: bool,
+    turbo_rate: f64,
+    sticky_keys: Vec<Event>,
+    sticky_timeout: u64,
+    chord_timeout: u64,
+    sequence_timeout: u64,
+    double_tap_timeout: u64,
+    long_press_timeout: u64,
+    xkb_keymap: Option<String>,
+    unicode_input: bool,
+    remember_layout: bool,
+    persist_layout: bool,
+    mouse_stick: Option<MouseStick>,
+    gyro: Option<Gyro>,
+    touchpad: Option<TouchpadSettings>,
 }
 
 pub struct EventReader {
     config: Vec<Config>,
     stream: Arc<Mutex<EventStream>>,
+    motion_stream: Option<Arc<Mutex<EventStream>>>,
     virt_dev: Arc<Mutex<VirtualDevices>>,
     lstick_position: Arc<Mutex<Vec<i32>>>,
     rstick_position: Arc<Mutex<Vec<i32>>>,
     cursor_movement: Arc<Mutex<(i32, i32)>>,
     scroll_movement: Arc<Mutex<(i32, i32)>>,
+    mouse_movement: Arc<Mutex<(i32, i32)>>,
+    gyro_recalibrate: Arc<Mutex<bool>>,
     modifiers: Arc<Mutex<Vec<Event>>>,
     modifier_was_activated: Arc<Mutex<bool>>,
+    sticky_modifiers: Arc<Mutex<Vec<StickyModifier>>>,
+    sticky_notify: Notify,
     device_is_connected: Arc<Mutex<bool>>,
+    paused: Arc<Mutex<bool>>,
     active_layout: Arc<Mutex<u16>>,
+    previous_layout: Arc<Mutex<u16>>,
+    held_layers: Arc<Mutex<Vec<HeldLayer>>>,
+    layer_releases: Arc<Mutex<Vec<Event>>>,
+    layout_names: HashMap<u16, String>,
+    layout_memory: Arc<Mutex<HashMap<Client, u16>>>,
+    last_client: Arc<Mutex<Option<Client>>>,
     current_config: Arc<Mutex<Config>>,
     environment: Environment,
+    active_window: Arc<Mutex<Client>>,
+    gamepad: Arc<Mutex<Option<VirtualGamepad>>>,
+    tap_hold: Arc<Mutex<Vec<TapHoldState>>>,
+    tap_hold_buffer: Arc<Mutex<Vec<BufferedEvent>>>,
+    tap_hold_notify: Notify,
+    keyboard_leds: Arc<Mutex<KeyboardLeds>>,
+    led_notify: Notify,
+    turbo: Arc<Mutex<Vec<TurboState>>>,
+    turbo_toggled: Arc<Mutex<Vec<Event>>>,
+    turbo_notify: Notify,
+    toggled: Arc<Mutex<Vec<(Event, Toggle)>>>,
+    pending_chord: Arc<Mutex<Option<PendingChord>>>,
+    active_chords: Arc<Mutex<Vec<ActiveChord>>>,
+    chord_notify: Notify,
+    pending_sequence: Arc<Mutex<Option<PendingSequence>>>,
+    sequence_releases: Arc<Mutex<Vec<Event>>>,
+    sequence_notify: Notify,
+    timed_keys: Arc<Mutex<Vec<TimedKeyState>>>,
+    timed_keys_notify: Notify,
+    macros: Arc<Mutex<HashMap<Event, MacroTask>>>,
+    text_keymap: OnceLock<Arc<TextKeymap>>,
     settings: Settings,
 }
 
@@ -64,10 +314,28 @@ impl EventReader {
         config: Vec<Config>,
         virt_dev: Arc<Mutex<VirtualDevices>>,
         stream: Arc<Mutex<EventStream>>,
-        modifiers: Arc<Mutex<Vec<Event>>>,
-        modifier_was_activated: Arc<Mutex<bool>>,
-        environment: Environment,
-    ) -> Self {
+        motion_stream: Option<EventStream>,
+        shared_state: SharedState,
+    ) -> Result<Self, String> {
+        let SharedState {
+            environment,
+            active_window,
+            modifiers,
+            modifier_was_activated,
+            sticky_modifiers,
+            keyboard_leds,
+            gamepad,
+        } = shared_state;
+        for config in &config {
+            for (setting, value) in &config.settings {
+                match validate_setting(setting, value) {
+                    Err(error) if !error.starts_with("unknown setting") => {
+                        return Err(format!("{} in {}.toml", error, config.name))
+                    }
+                    _ => {}
+                }
+            }
+        }
         let mut position_vector: Vec<i32> = Vec::new();
         for i in [0, 0] {
             position_vector.push(i)
@@ -85,6 +353,14 @@ impl EventReader {
                 .unwrap()
                 .clone(),
         ));
+        let mut layout_names: HashMap<u16, String> = HashMap::new();
+        for config in &config {
+            if let Some(name) = config.settings.get("LAYOUT_NAME") {
+                layout_names
+                    .entry(config.associations.layout)
+                    .or_insert(name.clone());
+            }
+        }
         let lstick_function = config
             .iter()
             .find(|&x| x.associations == Associations::default())
@@ -101,7 +377,9 @@ impl EventReader {
             .get("LSTICK_SENSITIVITY")
             .unwrap_or(&"0".to_string())
             .parse::<u64>()
-            .expect("Invalid value for LSTICK_SENSITIVITY, please use an integer value >= 0");
+            .map_err(|_| {
+                "Invalid value for LSTICK_SENSITIVITY, please use an integer value >= 0".to_string()
+            })?;
         let lstick_deadzone: i32 = config
             .iter()
             .find(|&x| x.associations == Associations::default())
@@ -110,7 +388,10 @@ impl EventReader {
             .get("LSTICK_DEADZONE")
             .unwrap_or(&"5".to_string())
             .parse::<i32>()
-            .expect("Invalid value for LSTICK_DEADZONE, please use an integer between 0 and 128.");
+            .map_err(|_| {
+                "Invalid value for LSTICK_DEADZONE, please use an integer between 0 and 128."
+                    .to_string()
+            })?;
         let lstick_activation_modifiers: Vec<Event> = parse_modifiers(
             &config
                 .iter()
@@ -119,10 +400,50 @@ impl EventReader {
                 .settings,
             "LSTICK_ACTIVATION_MODIFIERS",
         );
+        let lstick_outer_deadzone: i32 = config
+            .iter()
+            .find(|&x| x.associations == Associations::default())
+            .unwrap()
+            .settings
+            .get("LSTICK_OUTER_DEADZONE")
+            .unwrap_or(&"0".to_string())
+            .parse::<i32>()
+            .map_err(|_| {
+                "Invalid value for LSTICK_OUTER_DEADZONE, please use an integer between 0 and 128."
+                    .to_string()
+            })?;
+        let lstick_radial_deadzone: bool = match config
+            .iter()
+            .find(|&x| x.associations == Associations::default())
+            .unwrap()
+            .settings
+            .get("LSTICK_DEADZONE_MODE")
+            .unwrap_or(&"axial".to_string())
+            .as_str()
+        {
+            "axial" => false,
+            "radial" => true,
+            _ => {
+                return Err("LSTICK_DEADZONE_MODE can only be \"axial\" or \"radial\".".to_string())
+            }
+        };
+        let lstick_curve: Curve = Curve::from_str(
+            config
+                .iter()
+                .find(|&x| x.associations == Associations::default())
+                .unwrap()
+                .settings
+                .get("LSTICK_CURVE")
+                .unwrap_or(&"linear".to_string()),
+        )
+        .map_err(|_| "Invalid value for LSTICK_CURVE.".to_string())?;
         let lstick = Stick {
             function: lstick_function,
             sensitivity: lstick_sensitivity,
             deadzone: lstick_deadzone,
+            outer_deadzone: lstick_outer_deadzone,
+            radial_deadzone: lstick_radial_deadzone,
+            curve: lstick_curve,
             activation_modifiers: lstick_activation_modifiers,
         };
 
@@ -142,7 +463,9 @@ impl EventReader {
             .get("RSTICK_SENSITIVITY")
             .unwrap_or(&"0".to_string())
             .parse::<u64>()
-            .expect("Invalid value for RSTICK_SENSITIVITY, please use an integer value >= 0");
+            .map_err(|_| {
+                "Invalid value for RSTICK_SENSITIVITY, please use an integer value >= 0".to_string()
+            })?;
         let rstick_deadzone: i32 = config
             .iter()
             .find(|&x| x.associations == Associations::default())
@@ -151,7 +474,10 @@ impl EventReader {
             .get("RSTICK_DEADZONE")
             .unwrap_or(&"5".to_string())
             .parse::<i32>()
-            .expect("Invalid value for RSTICK_DEADZONE, please use an integer between 0 and 128.");
+            .map_err(|_| {
+                "Invalid value for RSTICK_DEADZONE, please use an integer between 0 and 128."
+                    .to_string()
+            })?;
         let rstick_activation_modifiers: Vec<Event> = parse_modifiers(
             &config
                 .iter()
@@ -160,10 +486,50 @@ impl EventReader {
                 .settings,
             "RSTICK_ACTIVATION_MODIFIERS",
         );
+        let rstick_outer_deadzone: i32 = config
+            .iter()
+            .find(|&x| x.associations == Associations::default())
+            .unwrap()
+            .settings
+            .get("RSTICK_OUTER_DEADZONE")
+            .unwrap_or(&"0".to_string())
+            .parse::<i32>()
+            .map_err(|_| {
+                "Invalid value for RSTICK_OUTER_DEADZONE, please use an integer between 0 and 128."
+                    .to_string()
+            })?;
+        let rstick_radial_deadzone: bool = match config
+            .iter()
+            .find(|&x| x.associations == Associations::default())
+            .unwrap()
+            .settings
+            .get("RSTICK_DEADZONE_MODE")
+            .unwrap_or(&"axial".to_string())
+            .as_str()
+        {
+            "axial" => false,
+            "radial" => true,
+            _ => {
+                return Err("RSTICK_DEADZONE_MODE can only be \"axial\" or \"radial\".".to_string())
+            }
+        };
+        let rstick_curve: Curve = Curve::from_str(
+            config
+                .iter()
+                .find(|&x| x.associations == Associations::default())
+                .unwrap()
+                .settings
+                .get("RSTICK_CURVE")
+                .unwrap_or(&"linear".to_string()),
+        )
+        .map_err(|_| "Invalid value for RSTICK_CURVE.".to_string())?;
         let rstick = Stick {
             function: rstick_function,
             sensitivity: rstick_sensitivity,
             deadzone: rstick_deadzone,
+            outer_deadzone: rstick_outer_deadzone,
+            radial_deadzone: rstick_radial_deadzone,
+            curve: rstick_curve,
             activation_modifiers: rstick_activation_modifiers,
         };
 
@@ -175,7 +541,7 @@ impl EventReader {
             .get("16_BIT_AXIS")
             .unwrap_or(&"false".to_string())
             .parse()
-            .expect("16_BIT_AXIS can only be true or false.");
+            .map_err(|_| "16_BIT_AXIS can only be true or false.".to_string())?;
 
         let stadia: bool = config
             .iter()
@@ -185,7 +551,7 @@ impl EventReader {
             .get("STADIA")
             .unwrap_or(&"false".to_string())
             .parse()
-            .expect("STADIA can only be true or false.");
+            .map_err(|_| "STADIA can only be true or false.".to_string())?;
 
         let chain_only: bool = config
             .iter()
@@ -195,7 +561,7 @@ impl EventReader {
             .get("CHAIN_ONLY")
             .unwrap_or(&"true".to_string())
             .parse()
-            .expect("CHAIN_ONLY can only be true or false.");
+            .map_err(|_| "CHAIN_ONLY can only be true or false.".to_string())?;
 
         let invert_cursor_axis: bool = config
             .iter()
@@ -205,7 +571,7 @@ impl EventReader {
             .get("INVERT_CURSOR_AXIS")
             .unwrap_or(&"false".to_string())
             .parse()
-            .expect("INVERT_CURSOR_AXIS can only be true or false.");
+            .map_err(|_| "INVERT_CURSOR_AXIS can only be true or false.".to_string())?;
 
         let invert_scroll_axis: bool = config
             .iter()
@@ -215,7 +581,7 @@ impl EventReader {
             .get("INVERT_SCROLL_AXIS")
             .unwrap_or(&"false".to_string())
             .parse()
-            .expect("INVERT_SCROLL_AXIS can only be true or false.");
+            .map_err(|_| "INVERT_SCROLL_AXIS can only be true or false.".to_string())?;
 
         let cursor_speed: i32 = config
             .iter()
@@ -225,7 +591,9 @@ impl EventReader {
             .get("CURSOR_SPEED")
             .unwrap_or(&"0".to_string())
             .parse()
-            .expect("Invalid value for CURSOR_SPEED, please use an integer value.");
+            .map_err(|_| {
+                "Invalid value for CURSOR_SPEED, please use an integer value.".to_string()
+            })?;
 
         let cursor_acceleration: f32 = config
             .iter()
@@ -235,7 +603,10 @@ impl EventReader {
             .get("CURSOR_ACCEL")
             .unwrap_or(&"1".to_string())
             .parse()
-            .expect("Invalid value for CURSOR_ACCEL, please use an float value between 0 and 1.");
+            .map_err(|_| {
+                "Invalid value for CURSOR_ACCEL, please use an float value between 0 and 1."
+                    .to_string()
+            })?;
 
         let scroll_speed: i32 = config
             .iter()
@@ -245,7 +616,9 @@ impl EventReader {
             .get("SCROLL_SPEED")
             .unwrap_or(&"0".to_string())
             .parse()
-            .expect("Invalid value for SCROLL_SPEED, please use an integer value.");
+            .map_err(|_| {
+                "Invalid value for SCROLL_SPEED, please use an integer value.".to_string()
+            })?;
 
         let scroll_acceleration: f32 = config
             .iter()
@@ -255,7 +628,10 @@ impl EventReader {
             .get("SCROLL_ACCEL")
             .unwrap_or(&"1".to_string())
             .parse()
-            .expect("Invalid value for SCROLL_ACCEL, please use a float value between 0 and 1.");
+            .map_err(|_| {
+                "Invalid value for SCROLL_ACCEL, please use a float value between 0 and 1."
+                    .to_string()
+            })?;
 
         let cursor = Movement {
             speed: cursor_speed,
@@ -266,34 +642,15 @@ impl EventReader {
             speed: scroll_speed,
             acceleration: scroll_acceleration,
         };
-        let layout_switcher = if let Some(combination) = config
+        let layout_switcher = config
             .iter()
             .find(|&x| x.associations == Associations::default())
             .unwrap()
             .settings
             .get("LAYOUT_SWITCHER")
-        {
-            if let Some(sequence) = combination.rsplit_once("-") {
-                let mut mods: Vec<Event> = sequence
-                    .0
-                    .split("-")
-                    .map(|m| Event::Key(Key::from_str(m).expect("LAYOUT_SWITCHER is invalid.")))
-                    .collect();
-                mods.sort();
-                mods.dedup();
-                Some((
-                    Event::Key(Key::from_str(sequence.1).expect("LAYOUT_SWITCHER is invalid.")),
-                    mods,
-                ))
-            } else {
-                Some((
-                    Event::Key(Key::from_str(combination).expect("LAYOUT_SWITCHER is invalid.")),
-                    Vec::new(),
-                ))
-            }
-        } else {
-            None
-        };
+            .map(|combination| parse_combination(combination))
+            .transpose()
+            .map_err(|error| format!("Invalid value for LAYOUT_SWITCHER: {}", error))?;
         let notify_layout_switch: bool = config
             .iter()
             .find(|&x| x.associations == Associations::default())
@@ -302,93 +659,561 @@ impl EventReader {
             .get("NOTIFY_LAYOUT_SWITCH")
             .unwrap_or(&"false".to_string())
             .parse()
-            .expect("NOTIFY_LAYOUT_SWITCH can only be true or false.");
-
-        let settings = Settings {
-            lstick,
-            rstick,
-            invert_cursor_axis,
-            invert_scroll_axis,
-            axis_16_bit,
-            stadia,
-            cursor,
-            scroll,
-            chain_only,
-            layout_switcher,
-            notify_layout_switch,
+            .map_err(|_| "NOTIFY_LAYOUT_SWITCH can only be true or false.".to_string())?;
+        let rumble_layout_switch: u16 = config
+            .iter()
+            .find(|&x| x.associations == Associations::default())
+            .unwrap()
+            .settings
+            .get("RUMBLE_LAYOUT_SWITCH")
+            .unwrap_or(&"0".to_string())
+            .parse()
+            .map_err(|_| "Invalid value for RUMBLE_LAYOUT_SWITCH, please use a duration in milliseconds between 0 and 65535.".to_string())?;
+        let layout_led: Option<LedType> = match config
+            .iter()
+            .find(|&x| x.associations == Associations::default())
+            .unwrap()
+            .settings
+            .get("LAYOUT_LED")
+            .unwrap_or(&"disabled".to_string())
+            .as_str()
+        {
+            "num-lock" => Some(LedType::LED_NUML),
+            "caps-lock" => Some(LedType::LED_CAPSL),
+            "scroll-lock" => Some(LedType::LED_SCROLLL),
+            "disabled" => None,
+            _ => return Err("LAYOUT_LED can only be \"num-lock\", \"caps-lock\", \"scroll-lock\" or \"disabled\".".to_string()),
         };
-        Self {
-            config,
-            stream,
-            virt_dev,
-            lstick_position,
-            rstick_position,
-            cursor_movement,
-            scroll_movement,
-            modifiers,
-            modifier_was_activated,
-            device_is_connected,
-            active_layout,
-            current_config,
-            environment,
-            settings,
-        }
-    }
-
-    pub async fn start(&self) {
-        println!(
-            "{:?} detected, reading events.\n",
-            self.config
+        let tap_hold_timeout: u64 = config
+            .iter()
+            .find(|&x| x.associations == Associations::default())
+            .unwrap()
+            .settings
+            .get("TAP_HOLD_TIMEOUT")
+            .unwrap_or(&"200".to_string())
+            .parse()
+            .map_err(|_| {
+                "Invalid value for TAP_HOLD_TIMEOUT, please use an integer value >= 0".to_string()
+            })?;
+        let turbo_rate: f64 = config
+            .iter()
+            .find(|&x| x.associations == Associations::default())
+            .unwrap()
+            .settings
+            .get("TURBO_RATE")
+            .unwrap_or(&"10".to_string())
+            .parse()
+            .map_err(|_| {
+                "Invalid value for TURBO_RATE, please use a float value > 0.".to_string()
+            })?;
+        let sticky_keys: Vec<Event> = parse_modifiers(
+            &config
                 .iter()
                 .find(|&x| x.associations == Associations::default())
                 .unwrap()
-                .name
-        );
-        tokio::join!(
-            self.event_loop(),
-            self.cursor_loop(),
-            self.scroll_loop(),
-            self.key_cursor_loop(),
-            self.key_scroll_loop()
+                .settings,
+            "STICKY_MODIFIERS",
         );
-    }
-
-    pub async fn event_loop(&self) {
-        let (
-            mut dpad_values,
-            mut lstick_values,
-            mut rstick_values,
-            mut triggers_values,
-            mut abs_wheel_position,
-        ) = ((0, 0), (0, 0), (0, 0), (0, 0), 0);
-        let mut stream = self.stream.lock().await;
-        let mut pen_events: Vec<InputEvent> = Vec::new();
-        let is_tablet: bool = stream
-            .device()
-            .supported_keys()
-            .unwrap_or(&evdev::AttributeSet::new())
-            .contains(Key::BTN_TOOL_PEN);
-        let mut max_abs_wheel = 0;
-        if let Ok(abs_state) = stream.device().get_abs_state() {
-            for state in abs_state {
-                if state.maximum > max_abs_wheel {
-                    max_abs_wheel = state.maximum;
-                }
+        let sticky_timeout: u64 = config
+            .iter()
+            .find(|&x| x.associations == Associations::default())
+            .unwrap()
+            .settings
+            .get("STICKY_TIMEOUT")
+            .unwrap_or(&"0".to_string())
+            .parse()
+            .map_err(|_| {
+                "Invalid value for STICKY_TIMEOUT, please use an integer value >= 0".to_string()
+            })?;
+        let chord_timeout: u64 = config
+            .iter()
+            .find(|&x| x.associations == Associations::default())
+            .unwrap()
+            .settings
+            .get("CHORD_TIMEOUT")
+            .unwrap_or(&"30".to_string())
+            .parse()
+            .map_err(|_| {
+                "Invalid value for CHORD_TIMEOUT, please use an integer value >= 0".to_string()
+            })?;
+        let sequence_timeout: u64 = config
+            .iter()
+            .find(|&x| x.associations == Associations::default())
+            .unwrap()
+            .settings
+            .get("SEQUENCE_TIMEOUT")
+            .unwrap_or(&"1000".to_string())
+            .parse()
+            .map_err(|_| {
+                "Invalid value for SEQUENCE_TIMEOUT, please use an integer value >= 0".to_string()
+            })?;
+        let double_tap_timeout: u64 = config
+            .iter()
+            .find(|&x| x.associations == Associations::default())
+            .unwrap()
+            .settings
+            .get("DOUBLE_TAP_TIMEOUT")
+            .unwrap_or(&"250".to_string())
+            .parse()
+            .map_err(|_| {
+                "Invalid value for DOUBLE_TAP_TIMEOUT, please use an integer value >= 0".to_string()
+            })?;
+        let long_press_timeout: u64 = config
+            .iter()
+            .find(|&x| x.associations == Associations::default())
+            .unwrap()
+            .settings
+            .get("LONG_PRESS_TIMEOUT")
+            .unwrap_or(&"500".to_string())
+            .parse()
+            .map_err(|_| {
+                "Invalid value for LONG_PRESS_TIMEOUT, please use an integer value >= 0".to_string()
+            })?;
+        let permissive_hold: bool = config
+            .iter()
+            .find(|&x| x.associations == Associations::default())
+            .unwrap()
+            .settings
+            .get("PERMISSIVE_HOLD")
+            .unwrap_or(&"false".to_string())
+            .parse()
+            .map_err(|_| "PERMISSIVE_HOLD can only be true or false.".to_string())?;
+        let xkb_keymap: Option<String> = config
+            .iter()
+            .find(|&x| x.associations == Associations::default())
+            .unwrap()
+            .settings
+            .get("XKB_KEYMAP")
+            .cloned();
+        let unicode_input: bool = match config
+            .iter()
+            .find(|&x| x.associations == Associations::default())
+            .unwrap()
+            .settings
+            .get("UNICODE_INPUT")
+            .unwrap_or(&"ctrl-shift-u".to_string())
+            .as_str()
+        {
+            "ctrl-shift-u" => true,
+            "disabled" => false,
+            _ => {
+                return Err(
+                    "UNICODE_INPUT can only be \"ctrl-shift-u\" or \"disabled\".".to_string(),
+                )
             }
-        }
-        while let Some(Ok(event)) = stream.next().await {
-            match (
-                event.event_type(),
-                RelativeAxisType(event.code()),
-                AbsoluteAxisType(event.code()),
-                is_tablet,
-            ) {
-                (EventType::KEY, _, _, _) => match Key(event.code()) {
-                    Key::BTN_TOOL_PEN
-                    | Key::BTN_TOOL_RUBBER
-                    | Key::BTN_TOOL_BRUSH
-                    | Key::BTN_TOOL_PENCIL
-                    | Key::BTN_TOOL_AIRBRUSH
+        };
+        let (remember_layout, persist_layout): (bool, bool) = match config
+            .iter()
+            .find(|&x| x.associations == Associations::default())
+            .unwrap()
+            .settings
+            .get("REMEMBER_LAYOUT")
+            .unwrap_or(&"false".to_string())
+            .as_str()
+        {
+            "false" => (false, false),
+            "true" => (true, false),
+            "persistent" => (true, true),
+            _ => {
+                return Err(
+                    "REMEMBER_LAYOUT can only be \"true\", \"false\" or \"persistent\"."
+                        .to_string(),
+                )
+            }
+        };
+        let mouse_stick_axes = match config
+            .iter()
+            .find(|&x| x.associations == Associations::default())
+            .unwrap()
+            .settings
+            .get("MOUSE_TO_STICK")
+            .unwrap_or(&"disabled".to_string())
+            .as_str()
+        {
+            "left" => Some((AbsoluteAxisType::ABS_X, AbsoluteAxisType::ABS_Y)),
+            "right" => Some((AbsoluteAxisType::ABS_RX, AbsoluteAxisType::ABS_RY)),
+            "disabled" => None,
+            _ => {
+                return Err(
+                    "MOUSE_TO_STICK can only be \"left\", \"right\" or \"disabled\".".to_string(),
+                )
+            }
+        };
+        let mouse_stick_sensitivity: f64 = config
+            .iter()
+            .find(|&x| x.associations == Associations::default())
+            .unwrap()
+            .settings
+            .get("MOUSE_STICK_SENSITIVITY")
+            .unwrap_or(&"1".to_string())
+            .parse()
+            .map_err(|_| {
+                "Invalid value for MOUSE_STICK_SENSITIVITY, please use a float value > 0."
+                    .to_string()
+            })?;
+        let mouse_stick_deadzone: f64 = config
+            .iter()
+            .find(|&x| x.associations == Associations::default())
+            .unwrap()
+            .settings
+            .get("MOUSE_STICK_DEADZONE")
+            .unwrap_or(&"0".to_string())
+            .parse()
+            .map_err(|_| {
+                "Invalid value for MOUSE_STICK_DEADZONE, please use a float value between 0 and 1."
+                    .to_string()
+            })?;
+        let mouse_stick_curve: f64 = config
+            .iter()
+            .find(|&x| x.associations == Associations::default())
+            .unwrap()
+            .settings
+            .get("MOUSE_STICK_CURVE")
+            .unwrap_or(&"1".to_string())
+            .parse()
+            .map_err(|_| {
+                "Invalid value for MOUSE_STICK_CURVE, please use a float value > 0.".to_string()
+            })?;
+        let mouse_stick_decay: u64 = config
+            .iter()
+            .find(|&x| x.associations == Associations::default())
+            .unwrap()
+            .settings
+            .get("MOUSE_STICK_DECAY")
+            .unwrap_or(&"50".to_string())
+            .parse()
+            .map_err(|_| {
+                "Invalid value for MOUSE_STICK_DECAY, please use an integer value >= 0".to_string()
+            })?;
+        let mouse_stick = mouse_stick_axes.map(|axes| MouseStick {
+            axes,
+            sensitivity: mouse_stick_sensitivity,
+            deadzone: mouse_stick_deadzone.clamp(0.0, 1.0),
+            curve: mouse_stick_curve,
+            decay: mouse_stick_decay,
+        });
+        let gyro_enabled: bool = match config
+            .iter()
+            .find(|&x| x.associations == Associations::default())
+            .unwrap()
+            .settings
+            .get("GYRO")
+            .unwrap_or(&"disabled".to_string())
+            .as_str()
+        {
+            "cursor" => true,
+            "disabled" => false,
+            _ => return Err("GYRO can only be \"cursor\" or \"disabled\".".to_string()),
+        };
+        let gyro_sensitivity: f64 = config
+            .iter()
+            .find(|&x| x.associations == Associations::default())
+            .unwrap()
+            .settings
+            .get("GYRO_SENSITIVITY")
+            .unwrap_or(&"10".to_string())
+            .parse()
+            .map_err(|_| {
+                "Invalid value for GYRO_SENSITIVITY, please use a float value.".to_string()
+            })?;
+        let gyro_smoothing: u64 = config
+            .iter()
+            .find(|&x| x.associations == Associations::default())
+            .unwrap()
+            .settings
+            .get("GYRO_SMOOTHING")
+            .unwrap_or(&"20".to_string())
+            .parse()
+            .map_err(|_| {
+                "Invalid value for GYRO_SMOOTHING, please use an integer value >= 0".to_string()
+            })?;
+        let gyro_activation_modifiers: Vec<Event> = parse_modifiers(
+            &config
+                .iter()
+                .find(|&x| x.associations == Associations::default())
+                .unwrap()
+                .settings,
+            "GYRO_ACTIVATION_MODIFIERS",
+        );
+        let gyro_recalibrate = config
+            .iter()
+            .find(|&x| x.associations == Associations::default())
+            .unwrap()
+            .settings
+            .get("GYRO_RECALIBRATE")
+            .map(|combination| parse_combination(combination))
+            .transpose()
+            .map_err(|error| format!("Invalid value for GYRO_RECALIBRATE: {}", error))?;
+        let gyro = if gyro_enabled {
+            Some(Gyro {
+                sensitivity: gyro_sensitivity,
+                smoothing: gyro_smoothing,
+                activation_modifiers: gyro_activation_modifiers,
+                recalibrate: gyro_recalibrate,
+            })
+        } else {
+            None
+        };
+        let touchpad_mode: Option<bool> = match config
+            .iter()
+            .find(|&x| x.associations == Associations::default())
+            .unwrap()
+            .settings
+            .get("TOUCHPAD")
+            .unwrap_or(&"disabled".to_string())
+            .as_str()
+        {
+            "mouse" => Some(true),
+            "gestures" => Some(false),
+            "disabled" => None,
+            _ => {
+                return Err(
+                    "TOUCHPAD can only be \"mouse\", \"gestures\" or \"disabled\".".to_string(),
+                )
+            }
+        };
+        let touchpad_speed: f64 = config
+            .iter()
+            .find(|&x| x.associations == Associations::default())
+            .unwrap()
+            .settings
+            .get("TOUCHPAD_SPEED")
+            .unwrap_or(&"1".to_string())
+            .parse()
+            .map_err(|_| {
+                "Invalid value for TOUCHPAD_SPEED, please use a float value.".to_string()
+            })?;
+        let touchpad_scroll_speed: f64 = config
+            .iter()
+            .find(|&x| x.associations == Associations::default())
+            .unwrap()
+            .settings
+            .get("TOUCHPAD_SCROLL_SPEED")
+            .unwrap_or(&"1".to_string())
+            .parse()
+            .map_err(|_| {
+                "Invalid value for TOUCHPAD_SCROLL_SPEED, please use a float value.".to_string()
+            })?;
+        let touchpad_natural_scroll: bool = config
+            .iter()
+            .find(|&x| x.associations == Associations::default())
+            .unwrap()
+            .settings
+            .get("TOUCHPAD_NATURAL_SCROLL")
+            .unwrap_or(&"true".to_string())
+            .parse()
+            .map_err(|_| "TOUCHPAD_NATURAL_SCROLL can only be true or false.".to_string())?;
+        let touchpad_tap_to_click: bool = config
+            .iter()
+            .find(|&x| x.associations == Associations::default())
+            .unwrap()
+            .settings
+            .get("TOUCHPAD_TAP_TO_CLICK")
+            .unwrap_or(&"true".to_string())
+            .parse()
+            .map_err(|_| "TOUCHPAD_TAP_TO_CLICK can only be true or false.".to_string())?;
+        let touchpad = touchpad_mode.map(|mouse| TouchpadSettings {
+            mouse,
+            speed: touchpad_speed,
+            scroll_speed: touchpad_scroll_speed,
+            natural_scroll: touchpad_natural_scroll,
+            tap_to_click: touchpad_tap_to_click,
+        });
+        let layout_memory: HashMap<Client, u16> = if persist_layout {
+            load_layout_memory(
+                &get_layout_memory_path(),
+                &config
+                    .iter()
+                    .find(|&x| x.associations == Associations::default())
+                    .unwrap()
+                    .name,
+            )
+        } else {
+            HashMap::new()
+        };
+
+        let settings = Settings {
+            lstick,
+            rstick,
+            invert_cursor_axis,
+            invert_scroll_axis,
+            axis_16_bit,
+            stadia,
+            cursor,
+            scroll,
+            chain_only,
+            layout_switcher,
+            notify_layout_switch,
+            rumble_layout_switch,
+            layout_led,
+            tap_hold_timeout,
+            permissive_hold,
+            turbo_rate,
+            sticky_keys,
+            sticky_timeout,
+            chord_timeout,
+            sequence_timeout,
+            double_tap_timeout,
+            long_press_timeout,
+            xkb_keymap,
+            unicode_input,
+            remember_layout,
+            persist_layout,
+            mouse_stick,
+            gyro,
+            touchpad,
+        };
+        Ok(Self {
+            config,
+            stream,
+            motion_stream: motion_stream.map(|stream| Arc::new(Mutex::new(stream))),
+            virt_dev,
+            lstick_position,
+            rstick_position,
+            cursor_movement,
+            scroll_movement,
+            mouse_movement: Arc::new(Mutex::new((0, 0))),
+            gyro_recalibrate: Arc::new(Mutex::new(false)),
+            modifiers,
+            modifier_was_activated,
+            device_is_connected,
+            paused: Arc::new(Mutex::new(false)),
+            active_layout,
+            previous_layout: Arc::new(Mutex::new(0)),
+            held_layers: Arc::new(Mutex::new(Vec::new())),
+            layer_releases: Arc::new(Mutex::new(Vec::new())),
+            layout_names,
+            layout_memory: Arc::new(Mutex::new(layout_memory)),
+            last_client: Arc::new(Mutex::new(None)),
+            current_config,
+            environment,
+            active_window,
+            gamepad,
+            tap_hold: Arc::new(Mutex::new(Vec::new())),
+            tap_hold_buffer: Arc::new(Mutex::new(Vec::new())),
+            tap_hold_notify: Notify::new(),
+            keyboard_leds,
+            led_notify: Notify::new(),
+            sticky_modifiers,
+            sticky_notify: Notify::new(),
+            turbo: Arc::new(Mutex::new(Vec::new())),
+            turbo_toggled: Arc::new(Mutex::new(Vec::new())),
+            turbo_notify: Notify::new(),
+            toggled: Arc::new(Mutex::new(Vec::new())),
+            pending_chord: Arc::new(Mutex::new(None)),
+            active_chords: Arc::new(Mutex::new(Vec::new())),
+            chord_notify: Notify::new(),
+            pending_sequence: Arc::new(Mutex::new(None)),
+            sequence_releases: Arc::new(Mutex::new(Vec::new())),
+            sequence_notify: Notify::new(),
+            timed_keys: Arc::new(Mutex::new(Vec::new())),
+            timed_keys_notify: Notify::new(),
+            macros: Arc::new(Mutex::new(HashMap::new())),
+            text_keymap: OnceLock::new(),
+            settings,
+        })
+    }
+
+    pub async fn start(&self) {
+        println!(
+            "{:?} detected, reading events.\n",
+            self.config
+                .iter()
+                .find(|&x| x.associations == Associations::default())
+                .unwrap()
+                .name
+        );
+        if self.config.iter().any(|config| config.uses_gamepad()) {
+            let mut gamepad = self.gamepad.lock().await;
+            if gamepad.is_none() {
+                *gamepad = Some(VirtualGamepad::new());
+            }
+            if self.virt_dev.lock().await.force_feedback().is_some() {
+                gamepad
+                    .as_ref()
+                    .unwrap()
+                    .add_force_feedback_target(&self.virt_dev);
+            }
+        }
+        if self.virt_dev.lock().await.mirrors_leds() {
+            self.keyboard_leds
+                .lock()
+                .await
+                .add_keyboard(&self.virt_dev)
+                .await;
+        }
+        tokio::join!(
+            self.event_loop(),
+            self.led_loop(),
+            self.cursor_loop(),
+            self.scroll_loop(),
+            self.key_cursor_loop(),
+            self.key_scroll_loop(),
+            self.tap_hold_loop(),
+            self.turbo_loop(),
+            self.sticky_loop(),
+            self.chord_loop(),
+            self.sequence_loop(),
+            self.timed_keys_loop(),
+            self.mouse_stick_loop(),
+            self.gyro_loop()
+        );
+    }
+
+    pub async fn event_loop(&self) {
+        let (
+            mut dpad_values,
+            mut lstick_values,
+            mut rstick_values,
+            mut triggers_values,
+            mut abs_wheel_position,
+        ) = ((0, 0), (0, 0), (0, 0), (0, 0), 0);
+        let mut stream = self.stream.lock().await;
+        let mut pen_events: Vec<InputEvent> = Vec::new();
+        let is_tablet: bool = stream
+            .device()
+            .supported_keys()
+            .unwrap_or(&evdev::AttributeSet::new())
+            .contains(Key::BTN_TOOL_PEN);
+        let mut max_abs_wheel = 0;
+        if let Ok(abs_state) = stream.device().get_abs_state() {
+            for state in abs_state {
+                if state.maximum > max_abs_wheel {
+                    max_abs_wheel = state.maximum;
+                }
+            }
+        }
+        let mut touchpad: Option<Touchpad> = self
+            .settings
+            .touchpad
+            .clone()
+            .map(|settings| Touchpad::new(settings, stream.device()));
+        while let Some(Ok(event)) = stream.next().await {
+            if *self.paused.lock().await {
+                self.emit_default_event(event).await;
+                continue;
+            }
+            if let Some(actions) = touchpad
+                .as_mut()
+                .and_then(|touchpad| touchpad.handle(&event))
+            {
+                for action in actions {
+                    self.emit_touchpad_action(event, action).await;
+                }
+                continue;
+            }
+            match (
+                event.event_type(),
+                RelativeAxisType(event.code()),
+                AbsoluteAxisType(event.code()),
+                is_tablet,
+            ) {
+                (EventType::KEY, _, _, _) => match Key(event.code()) {
+                    Key::BTN_TOOL_PEN
+                    | Key::BTN_TOOL_RUBBER
+                    | Key::BTN_TOOL_BRUSH
+                    | Key::BTN_TOOL_PENCIL
+                    | Key::BTN_TOOL_AIRBRUSH
                     | Key::BTN_TOOL_MOUSE
                     | Key::BTN_TOOL_LENS
                         if is_tablet =>
@@ -421,6 +1246,16 @@ impl EventReader {
                     }
                     _ => {}
                 },
+                (EventType::RELATIVE, RelativeAxisType::REL_X | RelativeAxisType::REL_Y, _, _)
+                    if self.settings.mouse_stick.is_some() =>
+                {
+                    let mut mouse_movement = self.mouse_movement.lock().await;
+                    if RelativeAxisType(event.code()) == RelativeAxisType::REL_X {
+                        mouse_movement.0 += event.value();
+                    } else {
+                        mouse_movement.1 += event.value();
+                    }
+                }
                 (EventType::ABSOLUTE, _, AbsoluteAxisType::ABS_WHEEL, _) => {
                     let value = event.value();
                     if value != 0 && abs_wheel_position != 0 {
@@ -534,9 +1369,7 @@ impl EventReader {
                     false,
                 ) => match self.settings.lstick.function.as_str() {
                     "cursor" | "scroll" => {
-                        let axis_value = self
-                            .get_axis_value(&event, &self.settings.lstick.deadzone)
-                            .await;
+                        let axis_value = self.get_distance_from_center(&event);
                         let mut lstick_position = self.lstick_position.lock().await;
                         lstick_position[event.code() as usize] = axis_value;
                     }
@@ -664,9 +1497,7 @@ impl EventReader {
                     false,
                 ) => match self.settings.rstick.function.as_str() {
                     "cursor" | "scroll" => {
-                        let axis_value = self
-                            .get_axis_value(&event, &self.settings.rstick.deadzone)
-                            .await;
+                        let axis_value = self.get_distance_from_center(&event);
                         let mut rstick_position = self.rstick_position.lock().await;
                         rstick_position[event.code() as usize - 3] = axis_value;
                     }
@@ -809,9 +1640,7 @@ impl EventReader {
                     } else {
                         match self.settings.rstick.function.as_str() {
                             "cursor" | "scroll" => {
-                                let axis_value = self
-                                    .get_axis_value(&event, &self.settings.rstick.deadzone)
-                                    .await;
+                                let axis_value = self.get_distance_from_center(&event);
                                 let mut rstick_position = self.rstick_position.lock().await;
                                 rstick_position[0] = axis_value;
                             }
@@ -900,9 +1729,7 @@ impl EventReader {
                     } else {
                         match self.settings.rstick.function.as_str() {
                             "cursor" | "scroll" => {
-                                let axis_value = self
-                                    .get_axis_value(&event, &self.settings.rstick.deadzone)
-                                    .await;
+                                let axis_value = self.get_distance_from_center(&event);
                                 let mut rstick_position = self.rstick_position.lock().await;
                                 rstick_position[1] = axis_value;
                             }
@@ -988,12 +1815,59 @@ impl EventReader {
         }
         let mut device_is_connected = self.device_is_connected.lock().await;
         *device_is_connected = false;
+        self.tap_hold_notify.notify_one();
+        self.led_notify.notify_one();
+        self.turbo_notify.notify_one();
+        self.sticky_notify.notify_one();
+        self.chord_notify.notify_one();
+        self.sequence_notify.notify_one();
+        self.timed_keys_notify.notify_one();
+        self.stop_macros().await;
 
         println!(
             "Disconnected device \"{}\".\n",
             self.current_config.lock().await.name
         );
     }
+    pub async fn stop_macros(&self) {
+        let held_keys = stop_macro_tasks(&self.macros).await;
+        let mut virt_dev = self.virt_dev.lock().await;
+        for key in held_keys {
+            let virtual_event: InputEvent = InputEvent::new_now(EventType::KEY, key.code(), 0);
+            virt_dev.emit_keys(&[virtual_event]).unwrap();
+        }
+    }
+    pub fn get_config_names(&self) -> Vec<String> {
+        self.config
+            .iter()
+            .map(|config| config.name.clone())
+            .collect()
+    }
+
+    pub async fn get_state(&self) -> (String, Client, bool) {
+        (
+            self.get_layout_name(*self.active_layout.lock().await),
+            get_active_window(&self.environment, &self.config, &self.active_window).await,
+            *self.paused.lock().await,
+        )
+    }
+
+    pub async fn set_layout(&self, layout: &LayoutId) -> Result<(), String> {
+        let layout = self.find_layout(layout)?;
+        let mut active_layout = self.active_layout.lock().await;
+        *self.previous_layout.lock().await = *active_layout;
+        *active_layout = layout;
+        drop(active_layout);
+        self.update_config().await;
+        Ok(())
+    }
+
+    pub async fn set_paused(&self, paused: bool) {
+        *self.paused.lock().await = paused;
+        *self.lstick_position.lock().await = vec![0, 0];
+        *self.rstick_position.lock().await = vec![0, 0];
+    }
+
     async fn convert_event(
         &self,
         default_event: InputEvent,
@@ -1004,35 +1878,237 @@ impl EventReader {
         if value == 1 {
             self.update_config().await;
         };
-        let config = self.current_config.lock().await;
-        let modifiers = self.modifiers.lock().await.clone();
-        if let Some(map) = config.bindings.remap.get(&event) {
-            if let Some(event_list) = map.get(&modifiers) {
-                self.emit_event(
-                    event_list,
-                    value,
-                    &modifiers,
-                    &config,
-                    modifiers.is_empty(),
-                    !modifiers.is_empty(),
-                )
+        for (default_event, event, value, send_zero) in self
+            .handle_sequence(default_event, event, value, send_zero)
+            .await
+        {
+            self.process_chords(default_event, event, value, send_zero)
                 .await;
-                if send_zero {
-                    let modifiers = self.modifiers.lock().await.clone();
-                    self.emit_event(
-                        event_list,
-                        0,
-                        &modifiers,
-                        &config,
-                        modifiers.is_empty(),
-                        !modifiers.is_empty(),
-                    )
-                    .await;
-                }
-                return;
-            }
-            if let Some(event_list) = map.get(&vec![Event::Hold]) {
-                if !modifiers.is_empty() || self.settings.chain_only == false {
+        }
+    }
+
+    async fn process_chords(
+        &self,
+        default_event: InputEvent,
+        event: Event,
+        value: i32,
+        send_zero: bool,
+    ) {
+        for (default_event, event, value, send_zero) in self
+            .handle_chord(default_event, event, value, send_zero)
+            .await
+        {
+            self.process_event(default_event, event, value, send_zero)
+                .await;
+        }
+    }
+
+    async fn process_event(
+        &self,
+        default_event: InputEvent,
+        event: Event,
+        value: i32,
+        send_zero: bool,
+    ) {
+        for (default_event, event, value, send_zero) in self
+            .handle_timed_key(default_event, event, value, send_zero)
+            .await
+        {
+            self.dispatch_event(default_event, event, value, send_zero)
+                .await;
+        }
+    }
+
+    async fn dispatch_event(
+        &self,
+        default_event: InputEvent,
+        event: Event,
+        value: i32,
+        send_zero: bool,
+    ) {
+        if self.handle_sticky_modifier(event, value).await {
+            return;
+        }
+        self.dispatch_binding(default_event, event, value, send_zero)
+            .await;
+        if value == 1 {
+            self.consume_sticky_modifiers(event).await;
+        }
+    }
+
+    async fn dispatch_binding(
+        &self,
+        default_event: InputEvent,
+        event: Event,
+        value: i32,
+        send_zero: bool,
+    ) {
+        if self
+            .handle_tap_hold(default_event, event, value, send_zero)
+            .await
+        {
+            self.flush_tap_hold_buffer().await;
+            return;
+        }
+        if self.track_layer_press(default_event, event, value).await {
+            return;
+        }
+        if value == 0 && self.release_layer(event).await {
+            return;
+        }
+        if value == 0 {
+            if let Some(gamepad) = self.gamepad.lock().await.as_mut() {
+                if gamepad.release(event) {
+                    return;
+                }
+            }
+        }
+        if value == 0 && self.release_turbo(event).await {
+            return;
+        }
+        if value == 1 && self.release_toggle(event).await {
+            return;
+        }
+        let config = self.current_config.lock().await;
+        let modifiers = self.modifiers.lock().await.clone();
+        let turbo_toggled = self.turbo_toggled.lock().await.contains(&event);
+        if let Some(target) = config
+            .bindings
+            .turbo_toggles
+            .get(&event)
+            .and_then(|map| map.get(&modifiers))
+        {
+            if value == 1 {
+                let target = *target;
+                drop(config);
+                let mut toggled = self.turbo_toggled.lock().await;
+                if let Some(index) = toggled.iter().position(|x| *x == target) {
+                    toggled.remove(index);
+                } else {
+                    toggled.push(target);
+                }
+                drop(toggled);
+                self.release_turbo(target).await;
+            }
+            return;
+        }
+        if let Some(turbo) = config
+            .bindings
+            .turbo
+            .get(&event)
+            .and_then(|map| map.get(&modifiers))
+        {
+            if !turbo_toggled {
+                if value == 1 {
+                    self.start_turbo(event, &turbo.keys, turbo.rate, &modifiers, &config)
+                        .await;
+                }
+            } else {
+                self.emit_event(&turbo.keys, value, &modifiers, &config, true, false)
+                    .await;
+            }
+            return;
+        }
+        if let Some(toggle) = config
+            .bindings
+            .toggles
+            .get(&event)
+            .and_then(|map| map.get(&modifiers))
+        {
+            if value == 1 {
+                match toggle {
+                    Toggle::Keys(keys) => {
+                        self.emit_event(keys, 1, &modifiers, &config, true, false)
+                            .await
+                    }
+                    Toggle::Movement(movement) => self.emit_movement(movement, 1).await,
+                }
+                self.toggled.lock().await.push((event, toggle.clone()));
+            }
+            return;
+        }
+        if let Some(binding) = config
+            .bindings
+            .macros
+            .get(&event)
+            .and_then(|map| map.get(&modifiers))
+        {
+            if value == 1 {
+                self.run_macro(event, binding, &modifiers).await;
+            }
+            return;
+        }
+        if let Some(action) = config
+            .bindings
+            .layouts
+            .get(&event)
+            .and_then(|map| map.get(&modifiers))
+        {
+            if value == 1 {
+                let action = action.clone();
+                drop(config);
+                self.run_layout_action(event, action).await;
+            }
+            return;
+        }
+        if let Some(rumble) = config
+            .bindings
+            .rumble
+            .get(&event)
+            .and_then(|map| map.get(&modifiers))
+        {
+            if value == 1 {
+                self.rumble(rumble.duration, rumble.strength).await;
+            }
+            return;
+        }
+        if let Some(output) = config
+            .bindings
+            .gamepad
+            .get(&event)
+            .and_then(|map| map.get(&modifiers))
+        {
+            if value == 1 {
+                if let Some(gamepad) = self.gamepad.lock().await.as_mut() {
+                    gamepad.press(event, *output);
+                }
+            }
+            return;
+        }
+        if let Some(map) = config.bindings.remap.get(&event) {
+            if let Some(event_list) = map.get(&modifiers) {
+                if turbo_toggled {
+                    if value == 1 {
+                        self.start_turbo(event, event_list, None, &modifiers, &config)
+                            .await;
+                    }
+                    return;
+                }
+                self.emit_event(
+                    event_list,
+                    value,
+                    &modifiers,
+                    &config,
+                    modifiers.is_empty(),
+                    !modifiers.is_empty(),
+                )
+                .await;
+                if send_zero {
+                    let modifiers = self.modifiers.lock().await.clone();
+                    self.emit_event(
+                        event_list,
+                        0,
+                        &modifiers,
+                        &config,
+                        modifiers.is_empty(),
+                        !modifiers.is_empty(),
+                    )
+                    .await;
+                }
+                return;
+            }
+            if let Some(event_list) = map.get(&vec![Event::Hold]) {
+                if !modifiers.is_empty() || self.settings.chain_only == false {
                     self.emit_event(event_list, value, &modifiers, &config, false, false)
                         .await;
                     return;
@@ -1089,22 +2165,172 @@ impl EventReader {
                     if let Event::Key(key) = modifier {
                         let virtual_event: InputEvent =
                             InputEvent::new_now(EventType::KEY, key.code(), 0);
-                        virt_dev.keys.emit(&[virtual_event]).unwrap()
+                        virt_dev.emit_keys(&[virtual_event]).unwrap()
                     }
                 }
                 if let Event::Key(key) = event {
                     let virtual_event: InputEvent =
                         InputEvent::new_now(EventType::KEY, key.code(), 0);
-                    virt_dev.keys.emit(&[virtual_event]).unwrap()
+                    virt_dev.emit_keys(&[virtual_event]).unwrap()
                 }
+                drop(virt_dev);
                 self.change_active_layout().await;
                 return;
             }
         }
+        if let Some(Gyro {
+            recalibrate: Some(map),
+            ..
+        }) = &self.settings.gyro
+        {
+            if map.0 == event && map.1 == modifiers {
+                if value == 1 {
+                    *self.gyro_recalibrate.lock().await = true;
+                }
+                return;
+            }
+        }
+        if let (true, Event::Key(key)) = (turbo_toggled, event) {
+            if value == 1 {
+                self.start_turbo(event, &vec![key], None, &modifiers, &config)
+                    .await;
+            }
+            return;
+        }
         self.emit_nonmapped_event(default_event, event, value, &modifiers, &config)
             .await;
     }
 
+    async fn handle_sticky_modifier(&self, event: Event, value: i32) -> bool {
+        let Event::Key(key) = event else {
+            return false;
+        };
+        if !self.settings.sticky_keys.contains(&event) {
+            return false;
+        }
+        let mut sticky_modifiers = self.sticky_modifiers.lock().await;
+        let Some(index) = sticky_modifiers.iter().position(|sticky| sticky.key == key) else {
+            if value == 1 {
+                sticky_modifiers.push(StickyModifier {
+                    key,
+                    virt_dev: self.virt_dev.clone(),
+                    state: StickyState::new(),
+                });
+            }
+            return false;
+        };
+        let state = &mut sticky_modifiers[index].state;
+        match value {
+            1 => {
+                state.press(Instant::now());
+                true
+            }
+            0 => {
+                if !state.release(
+                    Instant::now(),
+                    self.settings.double_tap_timeout,
+                    self.settings.sticky_timeout,
+                ) {
+                    sticky_modifiers.remove(index);
+                    return false;
+                }
+                if !state.locked {
+                    self.sticky_notify.notify_one();
+                }
+                true
+            }
+            _ => true,
+        }
+    }
+
+    async fn consume_sticky_modifiers(&self, event: Event) {
+        if self.settings.sticky_keys.contains(&event)
+            || self.sticky_modifiers.lock().await.is_empty()
+            || self
+                .current_config
+                .lock()
+                .await
+                .mapped_modifiers
+                .all
+                .contains(&event)
+        {
+            return;
+        }
+        let mut released: Vec<(Key, Arc<Mutex<VirtualDevices>>)> = Vec::new();
+        self.sticky_modifiers.lock().await.retain_mut(|sticky| {
+            if sticky.state.consume() {
+                return true;
+            }
+            released.push((sticky.key, sticky.virt_dev.clone()));
+            false
+        });
+        for (key, virt_dev) in released {
+            self.release_sticky_modifier(key, &virt_dev).await;
+        }
+    }
+
+    async fn release_sticky_modifier(&self, key: Key, virt_dev: &Arc<Mutex<VirtualDevices>>) {
+        self.modifiers
+            .lock()
+            .await
+            .retain(|&modifier| modifier != Event::Key(key));
+        let virtual_event: InputEvent = InputEvent::new_now(EventType::KEY, key.code(), 0);
+        virt_dev.lock().await.emit_keys(&[virtual_event]).unwrap();
+    }
+
+    async fn start_turbo(
+        &self,
+        event: Event,
+        keys: &Vec<Key>,
+        rate: Option<f64>,
+        modifiers: &Vec<Event>,
+        config: &Config,
+    ) {
+        self.emit_event(keys, 1, modifiers, config, true, false)
+            .await;
+        let interval = get_turbo_interval(rate.unwrap_or(self.settings.turbo_rate));
+        self.turbo.lock().await.push(TurboState {
+            event,
+            keys: keys.clone(),
+            interval,
+            next: Instant::now() + interval,
+            pressed: true,
+        });
+        self.turbo_notify.notify_one();
+    }
+
+    async fn release_turbo(&self, event: Event) -> bool {
+        let config = self.current_config.lock().await;
+        let mut turbo = self.turbo.lock().await;
+        let Some(index) = turbo.iter().position(|state| state.event == event) else {
+            return false;
+        };
+        let state = turbo.remove(index);
+        if state.pressed {
+            let modifiers = self.modifiers.lock().await.clone();
+            self.emit_event(&state.keys, 0, &modifiers, &config, true, false)
+                .await;
+        }
+        self.turbo_notify.notify_one();
+        true
+    }
+
+    async fn release_toggle(&self, event: Event) -> bool {
+        let config = self.current_config.lock().await;
+        let Some(toggle) = take_toggle(&mut *self.toggled.lock().await, event) else {
+            return false;
+        };
+        match toggle {
+            Toggle::Keys(keys) => {
+                let modifiers = self.modifiers.lock().await.clone();
+                self.emit_event(&keys, 0, &modifiers, &config, true, false)
+                    .await;
+            }
+            Toggle::Movement(movement) => self.emit_movement(&movement, 0).await,
+        }
+        true
+    }
+
     async fn emit_event(
         &self,
         event_list: &Vec<Key>,
@@ -1123,7 +2349,7 @@ impl EventReader {
                     self.toggle_modifiers(Event::Key(key), 0, &config).await;
                     let virtual_event: InputEvent =
                         InputEvent::new_now(EventType::KEY, key.code(), 0);
-                    virt_dev.keys.emit(&[virtual_event]).unwrap();
+                    virt_dev.emit_keys(&[virtual_event]).unwrap();
                 }
             }
         } else if ignore_modifiers {
@@ -1131,7 +2357,7 @@ impl EventReader {
                 if let Event::Key(key) = key {
                     let virtual_event: InputEvent =
                         InputEvent::new_now(EventType::KEY, key.code(), 0);
-                    virt_dev.keys.emit(&[virtual_event]).unwrap();
+                    virt_dev.emit_keys(&[virtual_event]).unwrap();
                 }
             }
         }
@@ -1144,10 +2370,10 @@ impl EventReader {
                 if value == 0 && !*modifier_was_activated {
                     let virtual_event: InputEvent =
                         InputEvent::new_now(EventType::KEY, key.code(), 1);
-                    virt_dev.keys.emit(&[virtual_event]).unwrap();
+                    virt_dev.emit_keys(&[virtual_event]).unwrap();
                     let virtual_event: InputEvent =
                         InputEvent::new_now(EventType::KEY, key.code(), 0);
-                    virt_dev.keys.emit(&[virtual_event]).unwrap();
+                    virt_dev.emit_keys(&[virtual_event]).unwrap();
                     *modifier_was_activated = true;
                 } else if value == 1 {
                     *modifier_was_activated = false;
@@ -1155,12 +2381,400 @@ impl EventReader {
             } else {
                 let virtual_event: InputEvent =
                     InputEvent::new_now(EventType::KEY, key.code(), value);
-                virt_dev.keys.emit(&[virtual_event]).unwrap();
+                virt_dev.emit_keys(&[virtual_event]).unwrap();
                 *modifier_was_activated = true;
             }
         }
     }
 
+    async fn run_macro(&self, event: Event, binding: &Macro, modifiers: &Vec<Event>) {
+        let mut macros = self.macros.lock().await;
+        if let Some(MacroTask { task, held_keys }) = macros.remove(&event) {
+            if !task.is_finished() {
+                task.abort();
+                let _ = task.await;
+                let mut virt_dev = self.virt_dev.lock().await;
+                for key in held_keys.lock().await.drain(..) {
+                    let virtual_event: InputEvent =
+                        InputEvent::new_now(EventType::KEY, key.code(), 0);
+                    virt_dev.emit_keys(&[virtual_event]).unwrap();
+                }
+                return;
+            }
+        }
+        let mut virt_dev = self.virt_dev.lock().await;
+        for modifier in modifiers {
+            if let Event::Key(key) = modifier {
+                let virtual_event: InputEvent = InputEvent::new_now(EventType::KEY, key.code(), 0);
+                virt_dev.emit_keys(&[virtual_event]).unwrap();
+            }
+        }
+        drop(virt_dev);
+        let text_keymap = if binding
+            .steps
+            .iter()
+            .any(|step| matches!(step, MacroStep::Text(_)))
+        {
+            Some(
+                self.text_keymap
+                    .get_or_init(|| {
+                        Arc::new(TextKeymap::new(
+                            self.settings.xkb_keymap.as_ref(),
+                            self.settings.unicode_input,
+                        ))
+                    })
+                    .clone(),
+            )
+        } else {
+            None
+        };
+        let held_keys: Arc<Mutex<Vec<Key>>> = Arc::new(Mutex::new(Vec::new()));
+        let task = tokio::spawn(play_macro(
+            binding.clone(),
+            text_keymap,
+            self.virt_dev.clone(),
+            held_keys.clone(),
+        ));
+        macros.insert(event, MacroTask { task, held_keys });
+    }
+
+    async fn handle_tap_hold(
+        &self,
+        default_event: InputEvent,
+        event: Event,
+        value: i32,
+        send_zero: bool,
+    ) -> bool {
+        let mut tap_hold = self.tap_hold.lock().await;
+        if let Some(index) = tap_hold.iter().position(|state| state.event == event) {
+            if value == 0 {
+                let state = tap_hold.remove(index);
+                drop(tap_hold);
+                let config = self.current_config.lock().await;
+                let modifiers = self.modifiers.lock().await.clone();
+                if state.held {
+                    self.emit_event(&state.binding.hold, 0, &modifiers, &config, true, false)
+                        .await;
+                } else {
+                    self.emit_event(&state.binding.tap, 1, &modifiers, &config, true, false)
+                        .await;
+                    let modifiers = self.modifiers.lock().await.clone();
+                    self.emit_event(&state.binding.tap, 0, &modifiers, &config, true, false)
+                        .await;
+                }
+                self.tap_hold_notify.notify_one();
+            }
+            return true;
+        }
+        let config = self.current_config.lock().await;
+        if value == 1 {
+            for state in tap_hold.iter_mut() {
+                if state.interrupt() {
+                    let modifiers = self.modifiers.lock().await.clone();
+                    self.emit_event(&state.binding.hold, 1, &modifiers, &config, true, false)
+                        .await;
+                }
+            }
+        }
+        // Keys pressed or released while a tap-hold is undecided wait until it resolves,
+        // so that they're emitted after its tap or hold output, like in QMK.
+        let mut tap_hold_buffer = self.tap_hold_buffer.lock().await;
+        if tap_hold.iter().any(|state| !state.held) || !tap_hold_buffer.is_empty() {
+            if value != 2 {
+                tap_hold_buffer.push((default_event, event, value, send_zero));
+            }
+            return true;
+        }
+        drop(tap_hold_buffer);
+        if value != 1 {
+            return false;
+        }
+        let modifiers = self.modifiers.lock().await.clone();
+        if let Some(binding) = config
+            .bindings
+            .tap_hold
+            .get(&event)
+            .and_then(|map| map.get(&modifiers))
+        {
+            tap_hold.push(TapHoldState::new(
+                event,
+                binding,
+                self.settings.tap_hold_timeout,
+                self.settings.permissive_hold,
+                Instant::now(),
+            ));
+            self.tap_hold_notify.notify_one();
+            return true;
+        }
+        false
+    }
+
+    async fn handle_sequence(
+        &self,
+        default_event: InputEvent,
+        event: Event,
+        value: i32,
+        send_zero: bool,
+    ) -> Vec<(InputEvent, Event, i32, bool)> {
+        if !matches!(event, Event::Key(_)) {
+            return vec![(default_event, event, value, send_zero)];
+        }
+        let mut sequence_releases = self.sequence_releases.lock().await;
+        if let Some(index) = sequence_releases.iter().position(|x| *x == event) {
+            if value == 0 {
+                sequence_releases.remove(index);
+            }
+            return Vec::new();
+        }
+        drop(sequence_releases);
+        let mut pending_sequence = self.pending_sequence.lock().await;
+        let config = self.current_config.lock().await;
+        let Some(sequence) = pending_sequence.as_mut() else {
+            if value == 1
+                && config
+                    .bindings
+                    .sequences
+                    .iter()
+                    .any(|steps| steps[0] == event)
+            {
+                *pending_sequence = Some(PendingSequence {
+                    events: vec![(default_event, event, value, send_zero)],
+                    held: vec![event],
+                    deadline: Instant::now()
+                        + Duration::from_millis(self.settings.sequence_timeout),
+                });
+                self.sequence_notify.notify_one();
+                return Vec::new();
+            }
+            return vec![(default_event, event, value, send_zero)];
+        };
+        if value != 1 {
+            if !sequence.held.contains(&event) {
+                return vec![(default_event, event, value, send_zero)];
+            }
+            if value == 0 {
+                sequence.held.retain(|x| *x != event);
+                sequence
+                    .events
+                    .push((default_event, event, value, send_zero));
+            }
+            return Vec::new();
+        }
+        sequence
+            .events
+            .push((default_event, event, value, send_zero));
+        sequence.held.push(event);
+        let matched = match_sequence(&config.bindings.sequences, &get_typed_sequence(sequence));
+        drop(config);
+        if matched == SequenceMatch::Prefix {
+            sequence.deadline =
+                Instant::now() + Duration::from_millis(self.settings.sequence_timeout);
+            self.sequence_notify.notify_one();
+            return Vec::new();
+        }
+        let sequence = pending_sequence.take().unwrap();
+        self.sequence_notify.notify_one();
+        match matched {
+            SequenceMatch::Complete(id) => {
+                self.fire_sequence(id, sequence).await;
+                Vec::new()
+            }
+            _ => sequence.events,
+        }
+    }
+
+    async fn fire_sequence(&self, id: u16, sequence: PendingSequence) {
+        self.sequence_releases
+            .lock()
+            .await
+            .extend(sequence.held.iter().copied());
+        let (default_event, ..) = *sequence.events.last().unwrap();
+        self.dispatch_event(default_event, Event::Sequence(id), 1, false)
+            .await;
+        self.dispatch_event(default_event, Event::Sequence(id), 0, false)
+            .await;
+    }
+
+    async fn handle_chord(
+        &self,
+        default_event: InputEvent,
+        event: Event,
+        value: i32,
+        send_zero: bool,
+    ) -> Vec<(InputEvent, Event, i32, bool)> {
+        let mut active_chords = self.active_chords.lock().await;
+        if let Some(index) = active_chords
+            .iter()
+            .position(|chord| chord.keys.contains(&event))
+        {
+            if value == 0 {
+                let chord = &mut active_chords[index];
+                chord.keys.retain(|key| *key != event);
+                if !chord.released {
+                    chord.released = true;
+                    let config = self.current_config.lock().await;
+                    let modifiers = self.modifiers.lock().await.clone();
+                    self.emit_event(&chord.output, 0, &modifiers, &config, true, false)
+                        .await;
+                }
+                if chord.keys.is_empty() {
+                    active_chords.remove(index);
+                }
+            }
+            return Vec::new();
+        }
+        drop(active_chords);
+        let mut pending_chord = self.pending_chord.lock().await;
+        let config = self.current_config.lock().await;
+        let Some(chord) = pending_chord.as_mut() else {
+            let modifiers = self.modifiers.lock().await.clone();
+            if value == 1
+                && config
+                    .bindings
+                    .chords
+                    .iter()
+                    .any(|(keys, map)| keys.contains(&event) && map.contains_key(&modifiers))
+            {
+                *pending_chord = Some(PendingChord {
+                    events: vec![(default_event, event, send_zero)],
+                    modifiers,
+                    deadline: Instant::now() + Duration::from_millis(self.settings.chord_timeout),
+                });
+                self.chord_notify.notify_one();
+                return Vec::new();
+            }
+            return vec![(default_event, event, value, send_zero)];
+        };
+        if value == 2
+            && chord
+                .events
+                .iter()
+                .any(|(_, pending_event, _)| *pending_event == event)
+        {
+            return Vec::new();
+        }
+        if value == 1 {
+            let mut keys: Vec<Event> = chord
+                .events
+                .iter()
+                .map(|(_, pending_event, _)| *pending_event)
+                .chain([event])
+                .collect();
+            keys.sort();
+            if let Some(output) = config
+                .bindings
+                .chords
+                .get(&keys)
+                .and_then(|map| map.get(&chord.modifiers))
+            {
+                let modifiers = self.modifiers.lock().await.clone();
+                self.emit_event(output, 1, &modifiers, &config, true, false)
+                    .await;
+                self.active_chords.lock().await.push(ActiveChord {
+                    keys,
+                    output: output.clone(),
+                    released: false,
+                });
+                *pending_chord = None;
+                self.chord_notify.notify_one();
+                return Vec::new();
+            }
+            if config.bindings.chords.iter().any(|(chord_keys, map)| {
+                keys.iter().all(|key| chord_keys.contains(key))
+                    && map.contains_key(&chord.modifiers)
+            }) {
+                chord.events.push((default_event, event, send_zero));
+                return Vec::new();
+            }
+        }
+        let mut events: Vec<(InputEvent, Event, i32, bool)> = pending_chord
+            .take()
+            .unwrap()
+            .events
+            .into_iter()
+            .map(|(default_event, event, send_zero)| (default_event, event, 1, send_zero))
+            .collect();
+        events.push((default_event, event, value, send_zero));
+        self.chord_notify.notify_one();
+        events
+    }
+
+    async fn handle_timed_key(
+        &self,
+        default_event: InputEvent,
+        event: Event,
+        value: i32,
+        send_zero: bool,
+    ) -> Vec<(InputEvent, Event, i32, bool)> {
+        let Event::Key(key) = event else {
+            return vec![(default_event, event, value, send_zero)];
+        };
+        let mut timed_keys = self.timed_keys.lock().await;
+        let mut events: Vec<(InputEvent, Event, i32, bool)> = Vec::new();
+        if value == 1 {
+            timed_keys.retain(|state| {
+                if state.key == key || state.fired.is_some() {
+                    return true;
+                }
+                events.extend(settle_timed_key(state));
+                false
+            });
+        }
+        if let Some(index) = timed_keys.iter().position(|state| state.key == key) {
+            let state = &mut timed_keys[index];
+            match (value, state.fired) {
+                (0, Some(fired)) => {
+                    events.push((default_event, fired, 0, false));
+                    timed_keys.remove(index);
+                }
+                (0, None) if state.double_tap => {
+                    state.pressed = false;
+                    state.deadline = Some(
+                        Instant::now() + Duration::from_millis(self.settings.double_tap_timeout),
+                    );
+                    self.timed_keys_notify.notify_one();
+                }
+                (0, None) => {
+                    events.extend(settle_timed_key(state));
+                    events.push((default_event, event, value, send_zero));
+                    timed_keys.remove(index);
+                }
+                (1, None) if !state.pressed => {
+                    state.pressed = true;
+                    state.deadline = None;
+                    state.fired = Some(Event::DoubleTap(key));
+                    events.push((default_event, Event::DoubleTap(key), 1, false));
+                }
+                _ => {}
+            }
+            return events;
+        }
+        let config = self.current_config.lock().await;
+        let double_tap = config.bindings.contains(&Event::DoubleTap(key));
+        let long_press = config.bindings.contains(&Event::LongPress(key));
+        if value != 1 || !(double_tap || long_press) {
+            events.push((default_event, event, value, send_zero));
+            return events;
+        }
+        let timeout = if long_press {
+            self.settings.long_press_timeout
+        } else {
+            self.settings.double_tap_timeout
+        };
+        timed_keys.push(TimedKeyState {
+            key,
+            default_event,
+            send_zero,
+            pressed: true,
+            double_tap,
+            long_press,
+            deadline: Some(Instant::now() + Duration::from_millis(timeout)),
+            fired: None,
+        });
+        self.timed_keys_notify.notify_one();
+        events
+    }
+
     async fn emit_nonmapped_event(
         &self,
         default_event: InputEvent,
@@ -1176,7 +2790,7 @@ impl EventReader {
             for key in released_keys {
                 self.toggle_modifiers(Event::Key(key), 0, &config).await;
                 let virtual_event: InputEvent = InputEvent::new_now(EventType::KEY, key.code(), 0);
-                virt_dev.keys.emit(&[virtual_event]).unwrap()
+                virt_dev.emit_keys(&[virtual_event]).unwrap()
             }
         }
         self.toggle_modifiers(event, value, &config).await;
@@ -1184,10 +2798,10 @@ impl EventReader {
             if value == 0 && !*modifier_was_activated {
                 let virtual_event: InputEvent =
                     InputEvent::new_now(default_event.event_type(), default_event.code(), 1);
-                virt_dev.keys.emit(&[virtual_event]).unwrap();
+                virt_dev.emit_keys(&[virtual_event]).unwrap();
                 let virtual_event: InputEvent =
                     InputEvent::new_now(default_event.event_type(), default_event.code(), 0);
-                virt_dev.keys.emit(&[virtual_event]).unwrap();
+                virt_dev.emit_keys(&[virtual_event]).unwrap();
                 *modifier_was_activated = true;
             } else if value == 1 {
                 *modifier_was_activated = false;
@@ -1196,7 +2810,7 @@ impl EventReader {
             *modifier_was_activated = true;
             match default_event.event_type() {
                 EventType::KEY => {
-                    virt_dev.keys.emit(&[default_event]).unwrap();
+                    virt_dev.emit_keys(&[default_event]).unwrap();
                 }
                 EventType::RELATIVE => {
                     virt_dev.axis.emit(&[default_event]).unwrap();
@@ -1217,7 +2831,7 @@ impl EventReader {
         match event.event_type() {
             EventType::KEY => {
                 let mut virt_dev = self.virt_dev.lock().await;
-                virt_dev.keys.emit(&[event]).unwrap();
+                virt_dev.emit_keys(&[event]).unwrap();
             }
             EventType::RELATIVE => {
                 let mut virt_dev = self.virt_dev.lock().await;
@@ -1235,6 +2849,33 @@ impl EventReader {
         }
     }
 
+    async fn emit_touchpad_action(&self, event: InputEvent, action: TouchpadAction) {
+        let (codes, (x, y)) = match action {
+            TouchpadAction::Move(x, y) => ((0, 1), (x, y)),
+            TouchpadAction::Scroll(x, y) => ((12, 11), (x, y)),
+            TouchpadAction::Button(button, value) => {
+                let event = InputEvent::new_now(EventType::KEY, button.code(), value);
+                self.convert_event(event, Event::Key(button), value, false)
+                    .await;
+                return;
+            }
+            TouchpadAction::Gesture(gesture) => {
+                self.convert_event(event, Event::Axis(gesture), 1, true)
+                    .await;
+                return;
+            }
+        };
+        let mut virtual_events: Vec<InputEvent> = Vec::new();
+        if x != 0 {
+            virtual_events.push(InputEvent::new_now(EventType::RELATIVE, codes.0, x));
+        }
+        if y != 0 {
+            virtual_events.push(InputEvent::new_now(EventType::RELATIVE, codes.1, y));
+        }
+        let mut virt_dev = self.virt_dev.lock().await;
+        virt_dev.axis.emit(&virtual_events).unwrap();
+    }
+
     async fn emit_movement(&self, movement: &Relative, value: i32) {
         let mut cursor_movement = self.cursor_movement.lock().await;
         let mut scroll_movement = self.scroll_movement.lock().await;
@@ -1299,86 +2940,555 @@ impl EventReader {
         }
     }
 
-    async fn get_axis_value(&self, event: &InputEvent, deadzone: &i32) -> i32 {
-        let distance_from_center: i32 = match self.settings.axis_16_bit {
-            false => (event.value() as i32 - 128) * 200,
-            _ => event.value() as i32,
-        };
-        if distance_from_center.abs() <= deadzone * 200 {
-            0
-        } else {
-            (distance_from_center + 2000 - 1) / 2000
+    fn get_distance_from_center(&self, event: &InputEvent) -> i32 {
+        match self.settings.axis_16_bit {
+            false => (event.value() as i32 - 128) * 200,
+            _ => event.value() as i32,
+        }
+    }
+
+    async fn get_axis_value(&self, event: &InputEvent, deadzone: &i32) -> i32 {
+        let distance_from_center: i32 = self.get_distance_from_center(event);
+        if distance_from_center.abs() <= deadzone * 200 {
+            0
+        } else {
+            (distance_from_center + 2000 - 1) / 2000
+        }
+    }
+
+    async fn toggle_modifiers(&self, modifier: Event, value: i32, config: &Config) {
+        let mut modifiers = self.modifiers.lock().await;
+        if config.mapped_modifiers.all.contains(&modifier) {
+            match value {
+                1 => {
+                    modifiers.push(modifier);
+                    modifiers.sort();
+                    modifiers.dedup();
+                }
+                0 => modifiers.retain(|&x| x != modifier),
+                _ => {}
+            }
+        }
+    }
+
+    async fn released_keys(&self, modifiers: &Vec<Event>, config: &Config) -> Vec<Key> {
+        let mut released_keys: Vec<Key> = Vec::new();
+        for (_key, hashmap) in config.bindings.remap.iter() {
+            if let Some(event_list) = hashmap.get(modifiers) {
+                released_keys.extend(event_list);
+            }
+        }
+        released_keys
+    }
+
+    fn get_layout_name(&self, layout: u16) -> String {
+        match self.layout_names.get(&layout) {
+            Some(name) => name.clone(),
+            None => layout.to_string(),
+        }
+    }
+
+    fn find_layout(&self, layout: &LayoutId) -> Result<u16, String> {
+        let found = match layout {
+            LayoutId::Number(number) => self
+                .config
+                .iter()
+                .any(|config| config.associations.layout == *number)
+                .then_some(*number),
+            LayoutId::Name(name) => self
+                .layout_names
+                .iter()
+                .find(|(_, layout_name)| *layout_name == name)
+                .map(|(number, _)| *number),
+        };
+        match (found, layout) {
+            (Some(layout), _) => Ok(layout),
+            (None, LayoutId::Number(number)) => {
+                Err(format!("no config file for layout {}", number))
+            }
+            (None, LayoutId::Name(name)) => Err(format!("no layout named {:?}", name)),
+        }
+    }
+
+    async fn run_layout_action(&self, event: Event, action: LayoutAction) {
+        let layout = match action {
+            LayoutAction::Switch(layout) => layout,
+            LayoutAction::Previous => LayoutId::Number(*self.previous_layout.lock().await),
+            LayoutAction::Hold(layout) => {
+                let layout = match self.find_layout(&layout) {
+                    Ok(layout) => layout,
+                    Err(error) => {
+                        println!("Warning: {}, unable to switch layout.\n", error);
+                        return;
+                    }
+                };
+                let previous_layout = *self.active_layout.lock().await;
+                self.held_layers.lock().await.push(HeldLayer {
+                    event,
+                    previous_layout,
+                    pressed: Vec::new(),
+                });
+                *self.active_layout.lock().await = layout;
+                self.update_config().await;
+                return;
+            }
+        };
+        match self.set_layout(&layout).await {
+            Ok(()) => self.notify_layout_switch().await,
+            Err(error) => println!("Warning: {}, unable to switch layout.\n", error),
+        }
+    }
+
+    async fn track_layer_press(&self, default_event: InputEvent, event: Event, value: i32) -> bool {
+        let mut layer_releases = self.layer_releases.lock().await;
+        if let Some(index) = layer_releases.iter().position(|x| *x == event) {
+            if value == 0 {
+                layer_releases.remove(index);
+            }
+            return true;
+        }
+        drop(layer_releases);
+        let mut held_layers = self.held_layers.lock().await;
+        match value {
+            1 => {
+                if let Some(layer) = held_layers.last_mut() {
+                    layer.pressed.push((default_event, event));
+                }
+            }
+            0 => {
+                for layer in held_layers.iter_mut() {
+                    layer.pressed.retain(|(_, pressed)| *pressed != event);
+                }
+            }
+            _ => {}
+        }
+        false
+    }
+
+    // Keys pressed while the layer was held are released through the layer's own
+    // bindings before switching back, and their physical release is ignored later.
+    fn release_layer(&self, event: Event) -> Pin<Box<dyn Future<Output = bool> + Send + '_>> {
+        Box::pin(async move {
+            let mut held_layers = self.held_layers.lock().await;
+            let Some(index) = held_layers.iter().position(|layer| layer.event == event) else {
+                return false;
+            };
+            let layer = held_layers.remove(index);
+            drop(held_layers);
+            for (default_event, pressed) in layer.pressed {
+                let release = InputEvent::new(default_event.event_type(), default_event.code(), 0);
+                self.dispatch_binding(release, pressed, 0, false).await;
+                self.layer_releases.lock().await.push(pressed);
+            }
+            *self.active_layout.lock().await = layer.previous_layout;
+            self.update_config().await;
+            true
+        })
+    }
+
+    async fn notify_layout_switch(&self) {
+        if self.settings.notify_layout_switch {
+            let notify = vec![format!(
+                "notify-send -t 500 'Makima' 'Switching to layout {}'",
+                self.get_layout_name(*self.active_layout.lock().await)
+            )];
+            self.spawn_subprocess(&notify).await;
+        }
+        if self.settings.rumble_layout_switch > 0 {
+            self.rumble(self.settings.rumble_layout_switch, 1.0).await;
+        }
+    }
+
+    async fn rumble(&self, duration: u16, strength: f64) {
+        let mut virt_dev = self.virt_dev.lock().await;
+        let Some(device) = virt_dev.force_feedback() else {
+            return;
+        };
+        let magnitude = (strength * u16::MAX as f64).round() as u16;
+        let effect_data = FFEffectData {
+            direction: 0,
+            trigger: FFTrigger::default(),
+            replay: FFReplay {
+                length: duration,
+                delay: 0,
+            },
+            kind: FFEffectKind::Rumble {
+                strong_magnitude: magnitude,
+                weak_magnitude: magnitude,
+            },
+        };
+        match device.upload_ff_effect(effect_data) {
+            Ok(mut effect) => {
+                if let Err(error) = effect.play(1) {
+                    println!("Warning: unable to play rumble effect: {}\n", error);
+                    return;
+                }
+                tokio::spawn(async move {
+                    tokio::time::sleep(Duration::from_millis(duration as u64)).await;
+                    drop(effect);
+                });
+            }
+            Err(error) => println!("Warning: unable to upload rumble effect: {}\n", error),
+        }
+    }
+
+    async fn change_active_layout(&self) {
+        let mut active_layout = self.active_layout.lock().await;
+        *self.previous_layout.lock().await = *active_layout;
+        let active_window =
+            get_active_window(&self.environment, &self.config, &self.active_window).await;
+        let mut layouts: Vec<u16> = self
+            .config
+            .iter()
+            .filter(|&x| x.associations.client == active_window)
+            .map(|x| x.associations.layout)
+            .collect();
+        layouts.sort();
+        layouts.dedup();
+        *active_layout = match layouts.iter().find(|&&layout| layout > *active_layout) {
+            Some(layout) => *layout,
+            None => layouts.first().copied().unwrap_or(0),
+        };
+        drop(active_layout);
+        self.notify_layout_switch().await;
+    }
+
+    fn update_config(&self) -> Pin<Box<dyn Future<Output = ()> + Send + '_>> {
+        Box::pin(async move {
+            let raw_active_window =
+                get_raw_active_window(&self.environment, &self.active_window).await;
+            if self.settings.remember_layout {
+                self.remember_layout(&raw_active_window).await;
+            }
+            let active_layout = self.active_layout.lock().await.clone();
+            let active_window = filter_active_window(&self.config, raw_active_window);
+            let associations = Associations {
+                client: active_window,
+                layout: active_layout,
+            };
+            match self.config.iter().find(|&x| x.associations == associations) {
+                Some(config) => {
+                    let mut current_config = self.current_config.lock().await;
+                    *current_config = config.clone();
+                    drop(current_config);
+                    if let Some(led) = self.settings.layout_led {
+                        self.virt_dev
+                            .lock()
+                            .await
+                            .set_layout_led(led, active_layout != 0);
+                    }
+                }
+                None => {
+                    self.change_active_layout().await;
+                    self.update_config().await;
+                }
+            };
+        })
+    }
+
+    async fn remember_layout(&self, client: &Client) {
+        if !self.held_layers.lock().await.is_empty() {
+            return;
+        }
+        let mut last_client = self.last_client.lock().await;
+        let mut active_layout = self.active_layout.lock().await;
+        let mut layout_memory = self.layout_memory.lock().await;
+        let mut changed = false;
+        if let Some(last_client) = last_client.as_ref() {
+            if layout_memory.get(last_client) != Some(&*active_layout) {
+                layout_memory.insert(last_client.clone(), *active_layout);
+                changed = true;
+            }
+        }
+        if last_client.as_ref() != Some(client) {
+            *active_layout = layout_memory.get(client).copied().unwrap_or(0);
+            *last_client = Some(client.clone());
+        }
+        if changed && self.settings.persist_layout {
+            let device = self
+                .config
+                .iter()
+                .find(|&x| x.associations == Associations::default())
+                .unwrap()
+                .name
+                .clone();
+            let layout_memory = layout_memory.clone();
+            let version = LAYOUT_MEMORY_VERSION.fetch_add(1, Ordering::Relaxed) + 1;
+            tokio::task::spawn_blocking(move || {
+                save_layout_memory(&get_layout_memory_path(), &device, &layout_memory, version)
+            });
+        }
+    }
+
+    pub async fn tap_hold_loop(&self) {
+        if self.config.iter().all(|x| x.bindings.tap_hold.is_empty()) {
+            return;
+        }
+        while *self.device_is_connected.lock().await {
+            let deadline = self
+                .tap_hold
+                .lock()
+                .await
+                .iter()
+                .filter(|state| !state.held)
+                .map(|state| state.deadline)
+                .min();
+            let Some(deadline) = deadline else {
+                self.tap_hold_notify.notified().await;
+                continue;
+            };
+            tokio::select! {
+                _ = tokio::time::sleep_until(deadline) => {}
+                _ = self.tap_hold_notify.notified() => continue,
+            }
+            let mut tap_hold = self.tap_hold.lock().await;
+            let config = self.current_config.lock().await;
+            let now = Instant::now();
+            for state in tap_hold.iter_mut() {
+                if state.expire(now) {
+                    let modifiers = self.modifiers.lock().await.clone();
+                    self.emit_event(&state.binding.hold, 1, &modifiers, &config, true, false)
+                        .await;
+                }
+            }
+            drop(config);
+            drop(tap_hold);
+            self.flush_tap_hold_buffer().await;
+        }
+    }
+
+    fn flush_tap_hold_buffer(&self) -> Pin<Box<dyn Future<Output = ()> + Send + '_>> {
+        Box::pin(async move {
+            if self.tap_hold.lock().await.iter().any(|state| !state.held) {
+                return;
+            }
+            let buffered = std::mem::take(&mut *self.tap_hold_buffer.lock().await);
+            for (default_event, event, value, send_zero) in buffered {
+                self.dispatch_binding(default_event, event, value, send_zero)
+                    .await;
+            }
+        })
+    }
+
+    pub async fn led_loop(&self) {
+        let mut led_events = match self.virt_dev.lock().await.keys.led_events() {
+            Ok(led_events) => led_events,
+            Err(error) => {
+                println!("Warning: unable to read keyboard LEDs: {}\n", error);
+                return;
+            }
+        };
+        while *self.device_is_connected.lock().await {
+            let leds = tokio::select! {
+                leds = led_events.next() => leds,
+                _ = self.led_notify.notified() => continue,
+            };
+            match leds {
+                Ok(leds) => self.keyboard_leds.lock().await.update(leds).await,
+                Err(error) => {
+                    println!("Warning: unable to read keyboard LEDs: {}\n", error);
+                    return;
+                }
+            }
+        }
+    }
+
+    pub async fn sticky_loop(&self) {
+        if self.settings.sticky_keys.is_empty() || self.settings.sticky_timeout == 0 {
+            return;
+        }
+        while *self.device_is_connected.lock().await {
+            let deadline = self
+                .sticky_modifiers
+                .lock()
+                .await
+                .iter()
+                .filter(|sticky| Arc::ptr_eq(&sticky.virt_dev, &self.virt_dev))
+                .filter_map(|sticky| sticky.state.deadline)
+                .min();
+            let Some(deadline) = deadline else {
+                self.sticky_notify.notified().await;
+                continue;
+            };
+            tokio::select! {
+                _ = tokio::time::sleep_until(deadline) => {}
+                _ = self.sticky_notify.notified() => continue,
+            }
+            let mut released: Vec<Key> = Vec::new();
+            self.sticky_modifiers.lock().await.retain(|sticky| {
+                let expired = Arc::ptr_eq(&sticky.virt_dev, &self.virt_dev)
+                    && sticky.state.expire(Instant::now());
+                if expired {
+                    released.push(sticky.key);
+                }
+                !expired
+            });
+            for key in released {
+                self.release_sticky_modifier(key, &self.virt_dev).await;
+            }
+        }
+    }
+
+    pub async fn turbo_loop(&self) {
+        if self
+            .config
+            .iter()
+            .all(|x| x.bindings.turbo.is_empty() && x.bindings.turbo_toggles.is_empty())
+        {
+            return;
         }
-    }
-
-    async fn toggle_modifiers(&self, modifier: Event, value: i32, config: &Config) {
-        let mut modifiers = self.modifiers.lock().await;
-        if config.mapped_modifiers.all.contains(&modifier) {
-            match value {
-                1 => {
-                    modifiers.push(modifier);
-                    modifiers.sort();
-                    modifiers.dedup();
+        while *self.device_is_connected.lock().await {
+            let deadline = self.turbo.lock().await.iter().map(|state| state.next).min();
+            let Some(deadline) = deadline else {
+                self.turbo_notify.notified().await;
+                continue;
+            };
+            tokio::select! {
+                _ = tokio::time::sleep_until(deadline) => {}
+                _ = self.turbo_notify.notified() => continue,
+            }
+            let config = self.current_config.lock().await;
+            let mut turbo = self.turbo.lock().await;
+            for state in turbo.iter_mut() {
+                if state.next <= Instant::now() {
+                    state.pressed = !state.pressed;
+                    state.next = Instant::now() + state.interval;
+                    let modifiers = self.modifiers.lock().await.clone();
+                    self.emit_event(
+                        &state.keys,
+                        state.pressed as i32,
+                        &modifiers,
+                        &config,
+                        true,
+                        false,
+                    )
+                    .await;
                 }
-                0 => modifiers.retain(|&x| x != modifier),
-                _ => {}
             }
         }
     }
 
-    async fn released_keys(&self, modifiers: &Vec<Event>, config: &Config) -> Vec<Key> {
-        let mut released_keys: Vec<Key> = Vec::new();
-        for (_key, hashmap) in config.bindings.remap.iter() {
-            if let Some(event_list) = hashmap.get(modifiers) {
-                released_keys.extend(event_list);
+    pub async fn chord_loop(&self) {
+        if self.config.iter().all(|x| x.bindings.chords.is_empty()) {
+            return;
+        }
+        while *self.device_is_connected.lock().await {
+            let deadline = self
+                .pending_chord
+                .lock()
+                .await
+                .as_ref()
+                .map(|chord| chord.deadline);
+            let Some(deadline) = deadline else {
+                self.chord_notify.notified().await;
+                continue;
+            };
+            tokio::select! {
+                _ = tokio::time::sleep_until(deadline) => {}
+                _ = self.chord_notify.notified() => continue,
+            }
+            let mut pending_chord = self.pending_chord.lock().await;
+            let Some(chord) = pending_chord.take_if(|chord| chord.deadline <= Instant::now())
+            else {
+                continue;
+            };
+            for (default_event, event, send_zero) in chord.events {
+                self.process_event(default_event, event, 1, send_zero).await;
             }
         }
-        released_keys
     }
 
-    async fn change_active_layout(&self) {
-        let mut active_layout = self.active_layout.lock().await;
-        let active_window = get_active_window(&self.environment, &self.config).await;
-        loop {
-            if *active_layout == 3 {
-                *active_layout = 0
-            } else {
-                *active_layout += 1
+    pub async fn sequence_loop(&self) {
+        if self.config.iter().all(|x| x.bindings.sequences.is_empty()) {
+            return;
+        }
+        while *self.device_is_connected.lock().await {
+            let deadline = self
+                .pending_sequence
+                .lock()
+                .await
+                .as_ref()
+                .map(|sequence| sequence.deadline);
+            let Some(deadline) = deadline else {
+                self.sequence_notify.notified().await;
+                continue;
             };
-            if let Some(_) = self.config.iter().find(|&x| {
-                x.associations.layout == *active_layout && x.associations.client == active_window
-            }) {
-                break;
+            tokio::select! {
+                _ = tokio::time::sleep_until(deadline) => {}
+                _ = self.sequence_notify.notified() => continue,
+            }
+            let mut pending_sequence = self.pending_sequence.lock().await;
+            let Some(sequence) =
+                pending_sequence.take_if(|sequence| sequence.deadline <= Instant::now())
+            else {
+                continue;
             };
-        }
-        if self.settings.notify_layout_switch {
-            let notify = vec![String::from(format!(
-                "notify-send -t 500 'Makima' 'Switching to layout {}'",
-                *active_layout
-            ))];
-            self.spawn_subprocess(&notify).await;
+            let typed = get_typed_sequence(&sequence);
+            let matched = self
+                .current_config
+                .lock()
+                .await
+                .bindings
+                .sequences
+                .iter()
+                .position(|steps| *steps == typed);
+            match matched {
+                Some(id) => self.fire_sequence(id as u16, sequence).await,
+                None => {
+                    for (default_event, event, value, send_zero) in sequence.events {
+                        self.process_chords(default_event, event, value, send_zero)
+                            .await;
+                    }
+                }
+            }
         }
     }
 
-    fn update_config(&self) -> Pin<Box<dyn Future<Output = ()> + Send + '_>> {
-        Box::pin(async move {
-            let active_layout = self.active_layout.lock().await.clone();
-            let active_window = get_active_window(&self.environment, &self.config).await;
-            let associations = Associations {
-                client: active_window,
-                layout: active_layout,
+    pub async fn timed_keys_loop(&self) {
+        if !self.config.iter().any(|x| {
+            x.bindings
+                .events()
+                .any(|event| matches!(event, Event::DoubleTap(_) | Event::LongPress(_)))
+        }) {
+            return;
+        }
+        while *self.device_is_connected.lock().await {
+            let deadline = self
+                .timed_keys
+                .lock()
+                .await
+                .iter()
+                .filter_map(|state| state.deadline)
+                .min();
+            let Some(deadline) = deadline else {
+                self.timed_keys_notify.notified().await;
+                continue;
             };
-            match self.config.iter().find(|&x| x.associations == associations) {
-                Some(config) => {
-                    let mut current_config = self.current_config.lock().await;
-                    *current_config = config.clone();
+            tokio::select! {
+                _ = tokio::time::sleep_until(deadline) => {}
+                _ = self.timed_keys_notify.notified() => continue,
+            }
+            let mut timed_keys = self.timed_keys.lock().await;
+            let mut events: Vec<(InputEvent, Event, i32, bool)> = Vec::new();
+            timed_keys.retain_mut(|state| {
+                match state.deadline {
+                    Some(deadline) if deadline <= Instant::now() => {}
+                    _ => return true,
                 }
-                None => {
-                    self.change_active_layout().await;
-                    self.update_config().await;
+                if state.pressed && state.long_press {
+                    state.deadline = None;
+                    state.fired = Some(Event::LongPress(state.key));
+                    events.push((state.default_event, Event::LongPress(state.key), 1, false));
+                    return true;
                 }
-            };
-        })
+                events.extend(settle_timed_key(state));
+                false
+            });
+            for (default_event, event, value, send_zero) in events {
+                self.dispatch_event(default_event, event, value, send_zero)
+                    .await;
+            }
+        }
     }
 
     pub async fn cursor_loop(&self) {
@@ -1398,6 +3508,7 @@ impl EventReader {
             } else {
                 ("disabled", 0, vec![])
             };
+        let mut remainder: (f64, f64) = (0.0, 0.0);
         if sensitivity != 0 {
             while *self.device_is_connected.lock().await {
                 {
@@ -1408,13 +3519,23 @@ impl EventReader {
                     } else {
                         break;
                     };
-                    if stick_position[0] != 0 || stick_position[1] != 0 {
+                    let stick = if cursor == "left" {
+                        &self.settings.lstick
+                    } else {
+                        &self.settings.rstick
+                    };
+                    let (x_movement, y_movement) =
+                        get_stick_movement(stick, &stick_position, self.settings.axis_16_bit);
+                    if x_movement != 0.0 || y_movement != 0.0 {
                         let modifiers = self.modifiers.lock().await;
                         if activation_modifiers.len() == 0 || activation_modifiers == *modifiers {
+                            remainder = (remainder.0 + x_movement, remainder.1 + y_movement);
+                            let (x_coord, y_coord) = (remainder.0.trunc(), remainder.1.trunc());
+                            remainder = (remainder.0 - x_coord, remainder.1 - y_coord);
                             let (x_coord, y_coord) = if self.settings.invert_cursor_axis {
-                                (-stick_position[0], -stick_position[1])
+                                (-x_coord as i32, -y_coord as i32)
                             } else {
-                                (stick_position[0], stick_position[1])
+                                (x_coord as i32, y_coord as i32)
                             };
                             let virtual_event_x: InputEvent =
                                 InputEvent::new_now(EventType::RELATIVE, 0, x_coord);
@@ -1424,6 +3545,8 @@ impl EventReader {
                             virt_dev.axis.emit(&[virtual_event_x]).unwrap();
                             virt_dev.axis.emit(&[virtual_event_y]).unwrap();
                         }
+                    } else {
+                        remainder = (0.0, 0.0);
                     }
                 }
                 tokio::time::sleep(std::time::Duration::from_millis(sensitivity)).await;
@@ -1450,6 +3573,7 @@ impl EventReader {
             } else {
                 ("disabled", 0, vec![])
             };
+        let mut remainder: (f64, f64) = (0.0, 0.0);
         if sensitivity != 0 {
             while *self.device_is_connected.lock().await {
                 {
@@ -1460,13 +3584,23 @@ impl EventReader {
                     } else {
                         break;
                     };
-                    if stick_position[0] != 0 || stick_position[1] != 0 {
+                    let stick = if scroll == "left" {
+                        &self.settings.lstick
+                    } else {
+                        &self.settings.rstick
+                    };
+                    let (x_movement, y_movement) =
+                        get_stick_movement(stick, &stick_position, self.settings.axis_16_bit);
+                    if x_movement != 0.0 || y_movement != 0.0 {
                         let modifiers = self.modifiers.lock().await;
                         if activation_modifiers.len() == 0 || activation_modifiers == *modifiers {
+                            remainder = (remainder.0 + x_movement, remainder.1 + y_movement);
+                            let (x_coord, y_coord) = (remainder.0.trunc(), remainder.1.trunc());
+                            remainder = (remainder.0 - x_coord, remainder.1 - y_coord);
                             let (x_coord, y_coord) = if self.settings.invert_scroll_axis {
-                                (-stick_position[0], -stick_position[1])
+                                (-x_coord as i32, -y_coord as i32)
                             } else {
-                                (stick_position[0], stick_position[1])
+                                (x_coord as i32, y_coord as i32)
                             };
                             let virtual_event_x: InputEvent =
                                 InputEvent::new_now(EventType::RELATIVE, 12, x_coord);
@@ -1476,6 +3610,8 @@ impl EventReader {
                             virt_dev.axis.emit(&[virtual_event_x]).unwrap();
                             virt_dev.axis.emit(&[virtual_event_y]).unwrap();
                         }
+                    } else {
+                        remainder = (0.0, 0.0);
                     }
                 }
                 tokio::time::sleep(std::time::Duration::from_millis(sensitivity)).await;
@@ -1485,6 +3621,142 @@ impl EventReader {
         }
     }
 
+    pub async fn mouse_stick_loop(&self) {
+        let Some(mouse_stick) = &self.settings.mouse_stick else {
+            return;
+        };
+        let decay = if mouse_stick.decay == 0 {
+            0.0
+        } else {
+            (-10.0 / mouse_stick.decay as f64).exp()
+        };
+        let (mut position, mut last_output) = ((0.0, 0.0), (0.0, 0.0));
+        while *self.device_is_connected.lock().await {
+            {
+                let (x, y) = std::mem::take(&mut *self.mouse_movement.lock().await);
+                let speed = (
+                    x as f64 * mouse_stick.sensitivity / 50.0,
+                    y as f64 * mouse_stick.sensitivity / 50.0,
+                );
+                position = (
+                    position.0 * decay + speed.0 * (1.0 - decay),
+                    position.1 * decay + speed.1 * (1.0 - decay),
+                );
+                let distance = f64::hypot(position.0, position.1);
+                let output = if distance < 0.005 {
+                    position = (0.0, 0.0);
+                    (0.0, 0.0)
+                } else {
+                    let magnitude = mouse_stick.deadzone
+                        + (1.0 - mouse_stick.deadzone) * distance.min(1.0).powf(mouse_stick.curve);
+                    (
+                        position.0 / distance * magnitude,
+                        position.1 / distance * magnitude,
+                    )
+                };
+                if output != last_output {
+                    if let Some(gamepad) = self.gamepad.lock().await.as_mut() {
+                        gamepad.move_stick(mouse_stick.axes, output);
+                    }
+                    last_output = output;
+                }
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+    }
+
+    pub async fn gyro_loop(&self) {
+        let (Some(gyro), Some(motion_stream)) = (&self.settings.gyro, &self.motion_stream) else {
+            return;
+        };
+        let mut stream = motion_stream.lock().await;
+        let resolution: (f64, f64) = match stream.device().get_abs_state() {
+            Ok(abs_state) => (
+                abs_state[AbsoluteAxisType::ABS_RX.0 as usize]
+                    .resolution
+                    .max(1) as f64,
+                abs_state[AbsoluteAxisType::ABS_RY.0 as usize]
+                    .resolution
+                    .max(1) as f64,
+            ),
+            Err(_) => (1.0, 1.0),
+        };
+        let (mut sample, mut bias, mut smoothed, mut remainder) =
+            ((0.0, 0.0), (0.0, 0.0), (0.0, 0.0), (0.0, 0.0));
+        let mut last_report: Option<std::time::SystemTime> = None;
+        let mut calibration = Some((Instant::now() + Duration::from_secs(1), (0.0, 0.0), 0));
+        while let Some(Ok(event)) = stream.next().await {
+            match (event.event_type(), AbsoluteAxisType(event.code())) {
+                (EventType::ABSOLUTE, AbsoluteAxisType::ABS_RX) => {
+                    sample.0 = event.value() as f64 / resolution.0
+                }
+                (EventType::ABSOLUTE, AbsoluteAxisType::ABS_RY) => {
+                    sample.1 = event.value() as f64 / resolution.1
+                }
+                (EventType::SYNCHRONIZATION, _) => {
+                    let elapsed = match last_report {
+                        Some(last_report) => event
+                            .timestamp()
+                            .duration_since(last_report)
+                            .unwrap_or_default()
+                            .as_secs_f64(),
+                        None => 0.0,
+                    };
+                    last_report = Some(event.timestamp());
+                    if std::mem::take(&mut *self.gyro_recalibrate.lock().await) {
+                        println!("Recalibrating the gyro, keep the controller still.\n");
+                        calibration =
+                            Some((Instant::now() + Duration::from_secs(1), (0.0, 0.0), 0));
+                    }
+                    if let Some((deadline, sum, count)) = &mut calibration {
+                        *sum = (sum.0 + sample.0, sum.1 + sample.1);
+                        *count += 1;
+                        if Instant::now() >= *deadline {
+                            bias = (sum.0 / *count as f64, sum.1 / *count as f64);
+                            calibration = None;
+                        }
+                        continue;
+                    }
+                    let smoothing = if gyro.smoothing == 0 {
+                        0.0
+                    } else {
+                        (-elapsed * 1000.0 / gyro.smoothing as f64).exp()
+                    };
+                    smoothed = (
+                        smoothed.0 * smoothing + (sample.0 - bias.0) * (1.0 - smoothing),
+                        smoothed.1 * smoothing + (sample.1 - bias.1) * (1.0 - smoothing),
+                    );
+                    let modifiers = self.modifiers.lock().await.clone();
+                    if *self.paused.lock().await
+                        || !(gyro.activation_modifiers.is_empty()
+                            || gyro.activation_modifiers == modifiers)
+                    {
+                        remainder = (0.0, 0.0);
+                        continue;
+                    }
+                    remainder = (
+                        remainder.0 - smoothed.1 * elapsed * gyro.sensitivity,
+                        remainder.1 - smoothed.0 * elapsed * gyro.sensitivity,
+                    );
+                    let (x_coord, y_coord) = (remainder.0.trunc(), remainder.1.trunc());
+                    remainder = (remainder.0 - x_coord, remainder.1 - y_coord);
+                    let mut virt_dev = self.virt_dev.lock().await;
+                    if x_coord != 0.0 {
+                        let virtual_event_x: InputEvent =
+                            InputEvent::new_now(EventType::RELATIVE, 0, x_coord as i32);
+                        virt_dev.axis.emit(&[virtual_event_x]).unwrap();
+                    }
+                    if y_coord != 0.0 {
+                        let virtual_event_y: InputEvent =
+                            InputEvent::new_now(EventType::RELATIVE, 1, y_coord as i32);
+                        virt_dev.axis.emit(&[virtual_event_y]).unwrap();
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
     pub async fn key_cursor_loop(&self) {
         let (speed, acceleration, mut current_speed) = (
             if self.settings.cursor.speed == 0 {
@@ -1580,3 +3852,552 @@ impl EventReader {
         }
     }
 }
+
+fn get_stick_movement(stick: &Stick, position: &[i32], axis_16_bit: bool) -> (f64, f64) {
+    let full_tilt: f64 = if axis_16_bit { 32768.0 } else { 25600.0 };
+    let (x, y) = (
+        (position[0] as f64 / full_tilt).clamp(-1.0, 1.0),
+        (position[1] as f64 / full_tilt).clamp(-1.0, 1.0),
+    );
+    let inner = stick.deadzone as f64 * 200.0 / full_tilt;
+    let outer = 1.0 - stick.outer_deadzone as f64 * 200.0 / full_tilt;
+    let scale = |distance: f64| -> f64 {
+        if distance <= inner {
+            0.0
+        } else if distance >= outer {
+            stick.curve.apply(1.0)
+        } else {
+            stick.curve.apply((distance - inner) / (outer - inner))
+        }
+    };
+    let (x, y) = if stick.radial_deadzone {
+        let distance = f64::hypot(x, y).min(1.0);
+        if distance == 0.0 {
+            (0.0, 0.0)
+        } else {
+            let magnitude = scale(distance) / distance;
+            (x * magnitude, y * magnitude)
+        }
+    } else {
+        (scale(x.abs()).copysign(x), scale(y.abs()).copysign(y))
+    };
+    (x * full_tilt / 2000.0, y * full_tilt / 2000.0)
+}
+
+fn get_typed_sequence(sequence: &PendingSequence) -> Vec<Event> {
+    sequence
+        .events
+        .iter()
+        .filter(|(_, _, value, _)| *value == 1)
+        .map(|(_, event, _, _)| *event)
+        .collect()
+}
+
+fn get_turbo_interval(rate: f64) -> Duration {
+    let rate = match rate {
+        rate if rate.is_nan() => 10.0,
+        rate => rate.clamp(0.01, 1000.0),
+    };
+    Duration::from_secs_f64(0.5 / rate)
+}
+
+// A typed sequence that could still grow into a longer one keeps waiting for the next key,
+// even if it already matches a shorter sequence.
+fn match_sequence(sequences: &[Vec<Event>], typed: &[Event]) -> SequenceMatch {
+    if sequences
+        .iter()
+        .any(|steps| steps.len() > typed.len() && steps.starts_with(typed))
+    {
+        return SequenceMatch::Prefix;
+    }
+    match sequences.iter().position(|steps| steps == typed) {
+        Some(id) => SequenceMatch::Complete(id as u16),
+        None => SequenceMatch::NoMatch,
+    }
+}
+
+fn settle_timed_key(state: &TimedKeyState) -> Vec<(InputEvent, Event, i32, bool)> {
+    let press = (
+        state.default_event,
+        Event::Key(state.key),
+        1,
+        state.send_zero,
+    );
+    if state.pressed {
+        return vec![press];
+    }
+    let release = InputEvent::new(
+        state.default_event.event_type(),
+        state.default_event.code(),
+        0,
+    );
+    vec![press, (release, Event::Key(state.key), 0, state.send_zero)]
+}
+
+fn take_toggle(toggled: &mut Vec<(Event, Toggle)>, event: Event) -> Option<Toggle> {
+    let index = toggled.iter().position(|(x, _)| *x == event)?;
+    Some(toggled.remove(index).1)
+}
+
+async fn stop_macro_tasks(macros: &Mutex<HashMap<Event, MacroTask>>) -> Vec<Key> {
+    let mut released_keys: Vec<Key> = Vec::new();
+    for (_event, MacroTask { task, held_keys }) in macros.lock().await.drain() {
+        task.abort();
+        let _ = task.await;
+        released_keys.extend(held_keys.lock().await.drain(..));
+    }
+    released_keys
+}
+
+async fn play_macro(
+    binding: Macro,
+    text_keymap: Option<Arc<TextKeymap>>,
+    virt_dev: Arc<Mutex<VirtualDevices>>,
+    held_keys: Arc<Mutex<Vec<Key>>>,
+) {
+    let mut iteration = 0;
+    while binding.repeat == 0 || iteration < binding.repeat {
+        for step in &binding.steps {
+            let (press, release): (&[Key], &[Key]) = match step {
+                MacroStep::Press(keys) => (keys, &[]),
+                MacroStep::Release(keys) => (&[], keys),
+                MacroStep::Tap(keys) => (keys, keys),
+                MacroStep::Sleep(ms) => {
+                    tokio::time::sleep(Duration::from_millis(*ms)).await;
+                    continue;
+                }
+                MacroStep::Text(text) => {
+                    let chords = text_keymap.as_ref().unwrap().get_chords(text);
+                    for chord in chords {
+                        let mut virt_dev = virt_dev.lock().await;
+                        for key in &chord {
+                            let virtual_event: InputEvent =
+                                InputEvent::new_now(EventType::KEY, key.code(), 1);
+                            virt_dev.emit_keys(&[virtual_event]).unwrap();
+                        }
+                        for key in chord.iter().rev() {
+                            let virtual_event: InputEvent =
+                                InputEvent::new_now(EventType::KEY, key.code(), 0);
+                            virt_dev.emit_keys(&[virtual_event]).unwrap();
+                        }
+                        drop(virt_dev);
+                        tokio::task::yield_now().await;
+                    }
+                    continue;
+                }
+            };
+            let mut held_keys = held_keys.lock().await;
+            let mut virt_dev = virt_dev.lock().await;
+            for key in press {
+                let virtual_event: InputEvent = InputEvent::new_now(EventType::KEY, key.code(), 1);
+                virt_dev.emit_keys(&[virtual_event]).unwrap();
+                held_keys.push(*key);
+            }
+            for key in release.iter().rev() {
+                let virtual_event: InputEvent = InputEvent::new_now(EventType::KEY, key.code(), 0);
+                virt_dev.emit_keys(&[virtual_event]).unwrap();
+                held_keys.retain(|held_key| held_key != key);
+            }
+        }
+        iteration += 1;
+        tokio::task::yield_now().await;
+    }
+    let mut held_keys = held_keys.lock().await;
+    let mut virt_dev = virt_dev.lock().await;
+    for key in held_keys.drain(..) {
+        let virtual_event: InputEvent = InputEvent::new_now(EventType::KEY, key.code(), 0);
+        virt_dev.emit_keys(&[virtual_event]).unwrap();
+    }
+}
+
+fn get_layout_memory_path() -> String {
+    let state_home = match std::env::var("XDG_STATE_HOME") {
+        Ok(state_home) => state_home,
+        Err(_) => format!("{}/.local/state", crate::get_user_home()),
+    };
+    format!("{}/makima/layouts.json", state_home.trim_end_matches("/"))
+}
+
+fn read_layout_memory_file(path: &str) -> HashMap<String, HashMap<String, u16>> {
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+fn load_layout_memory(path: &str, device: &str) -> HashMap<Client, u16> {
+    let _lock = LAYOUT_STATE_LOCK.lock().unwrap();
+    read_layout_memory_file(path)
+        .remove(device)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(client, layout)| match client.as_str() {
+            "" => (Client::Default, layout),
+            _ => (Client::Class(client), layout),
+        })
+        .collect()
+}
+
+fn save_layout_memory(
+    path: &str,
+    device: &str,
+    layout_memory: &HashMap<Client, u16>,
+    version: u64,
+) {
+    let mut saved_versions = LAYOUT_STATE_LOCK.lock().unwrap();
+    if saved_versions
+        .get(device)
+        .is_some_and(|&saved| saved > version)
+    {
+        return;
+    }
+    saved_versions.insert(device.to_string(), version);
+    let mut layout_memory_file = read_layout_memory_file(path);
+    layout_memory_file.insert(
+        device.to_string(),
+        layout_memory
+            .iter()
+            .map(|(client, layout)| match client {
+                Client::Default => (String::new(), *layout),
+                Client::Class(class) => (class.clone(), *layout),
+            })
+            .collect(),
+    );
+    let result = std::fs::create_dir_all(Path::new(path).parent().unwrap()).and_then(|_| {
+        std::fs::write(
+            path,
+            serde_json::to_string_pretty(&layout_memory_file).unwrap(),
+        )
+    });
+    if let Err(error) = result {
+        println!(
+            "Warning: unable to save the layout memory to {:?}: {}\n",
+            path, error
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timed_key(pressed: bool) -> TimedKeyState {
+        TimedKeyState {
+            key: Key::KEY_A,
+            default_event: InputEvent::new(EventType::KEY, Key::KEY_A.code(), 1),
+            send_zero: false,
+            pressed,
+            double_tap: true,
+            long_press: false,
+            deadline: None,
+            fired: None,
+        }
+    }
+
+    fn key_events(keys: &[Key]) -> Vec<Event> {
+        keys.iter().map(|key| Event::Key(*key)).collect()
+    }
+
+    #[test]
+    fn sequences_match_complete_and_partial_input() {
+        let sequences = vec![
+            key_events(&[Key::KEY_SPACE, Key::KEY_F]),
+            key_events(&[Key::KEY_SPACE, Key::KEY_B, Key::KEY_B]),
+        ];
+        assert_eq!(
+            match_sequence(&sequences, &key_events(&[Key::KEY_SPACE])),
+            SequenceMatch::Prefix
+        );
+        assert_eq!(
+            match_sequence(&sequences, &key_events(&[Key::KEY_SPACE, Key::KEY_F])),
+            SequenceMatch::Complete(0)
+        );
+        assert_eq!(
+            match_sequence(&sequences, &key_events(&[Key::KEY_SPACE, Key::KEY_B])),
+            SequenceMatch::Prefix
+        );
+        assert_eq!(
+            match_sequence(
+                &sequences,
+                &key_events(&[Key::KEY_SPACE, Key::KEY_B, Key::KEY_B])
+            ),
+            SequenceMatch::Complete(1)
+        );
+        assert_eq!(
+            match_sequence(&sequences, &key_events(&[Key::KEY_SPACE, Key::KEY_X])),
+            SequenceMatch::NoMatch
+        );
+    }
+
+    #[test]
+    fn sequences_wait_for_longer_matches() {
+        let sequences = vec![
+            key_events(&[Key::KEY_G, Key::KEY_G]),
+            key_events(&[Key::KEY_G, Key::KEY_G, Key::KEY_G]),
+        ];
+        assert_eq!(
+            match_sequence(&sequences, &key_events(&[Key::KEY_G, Key::KEY_G])),
+            SequenceMatch::Prefix
+        );
+        assert_eq!(
+            match_sequence(
+                &sequences,
+                &key_events(&[Key::KEY_G, Key::KEY_G, Key::KEY_G])
+            ),
+            SequenceMatch::Complete(1)
+        );
+    }
+
+    #[test]
+    fn typed_sequences_ignore_releases() {
+        let event = |key: Key, value: i32| {
+            (
+                InputEvent::new(EventType::KEY, key.code(), value),
+                Event::Key(key),
+                value,
+                false,
+            )
+        };
+        let sequence = PendingSequence {
+            events: vec![
+                event(Key::KEY_SPACE, 1),
+                event(Key::KEY_SPACE, 0),
+                event(Key::KEY_F, 1),
+            ],
+            held: vec![Event::Key(Key::KEY_F)],
+            deadline: Instant::now(),
+        };
+        assert_eq!(
+            get_typed_sequence(&sequence),
+            key_events(&[Key::KEY_SPACE, Key::KEY_F])
+        );
+    }
+
+    #[test]
+    fn turbo_intervals_are_half_a_press() {
+        assert_eq!(get_turbo_interval(10.0), Duration::from_millis(50));
+        assert_eq!(get_turbo_interval(0.5), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn turbo_intervals_never_panic() {
+        assert_eq!(get_turbo_interval(0.0), Duration::from_secs(50));
+        assert_eq!(get_turbo_interval(-5.0), Duration::from_secs(50));
+        assert_eq!(get_turbo_interval(f64::NAN), Duration::from_millis(50));
+        assert_eq!(
+            get_turbo_interval(f64::INFINITY),
+            Duration::from_micros(500)
+        );
+    }
+
+    fn tap_hold_binding(timeout: Option<u64>, permissive_hold: Option<bool>) -> TapHold {
+        TapHold {
+            tap: vec![Key::KEY_ESC],
+            hold: vec![Key::KEY_LEFTCTRL],
+            timeout,
+            permissive_hold,
+        }
+    }
+
+    #[test]
+    fn tap_holds_use_the_default_timeout_unless_overridden() {
+        let now = Instant::now();
+        let event = Event::Key(Key::KEY_CAPSLOCK);
+        let state = TapHoldState::new(event, &tap_hold_binding(None, None), 200, false, now);
+        assert_eq!(state.deadline, now + Duration::from_millis(200));
+        assert!(!state.permissive_hold);
+        let binding = tap_hold_binding(Some(300), Some(true));
+        let state = TapHoldState::new(event, &binding, 200, false, now);
+        assert_eq!(state.deadline, now + Duration::from_millis(300));
+        assert!(state.permissive_hold);
+    }
+
+    #[test]
+    fn tap_holds_turn_into_holds_once_the_timeout_expires() {
+        let now = Instant::now();
+        let binding = tap_hold_binding(None, None);
+        let mut state = TapHoldState::new(Event::Key(Key::KEY_CAPSLOCK), &binding, 200, false, now);
+        assert!(!state.expire(now + Duration::from_millis(199)));
+        assert!(!state.held);
+        assert!(state.expire(now + Duration::from_millis(200)));
+        assert!(state.held);
+        assert!(!state.expire(now + Duration::from_millis(400)));
+    }
+
+    #[test]
+    fn only_permissive_tap_holds_are_resolved_by_other_keys() {
+        let now = Instant::now();
+        let event = Event::Key(Key::KEY_CAPSLOCK);
+        let mut state = TapHoldState::new(event, &tap_hold_binding(None, None), 200, false, now);
+        assert!(!state.interrupt());
+        assert!(!state.held);
+        let mut state = TapHoldState::new(event, &tap_hold_binding(None, None), 200, true, now);
+        assert!(state.interrupt());
+        assert!(state.held);
+        assert!(!state.interrupt());
+    }
+
+    #[test]
+    fn settled_timed_keys_replay_the_original_key() {
+        let events = settle_timed_key(&timed_key(true));
+        assert_eq!(events.len(), 1);
+        assert_eq!((events[0].1, events[0].2), (Event::Key(Key::KEY_A), 1));
+    }
+
+    #[test]
+    fn settled_timed_keys_are_released_if_no_longer_held() {
+        let events = settle_timed_key(&timed_key(false));
+        assert_eq!(
+            events
+                .iter()
+                .map(|(default_event, event, value, _)| (*event, *value, default_event.value()))
+                .collect::<Vec<_>>(),
+            vec![
+                (Event::Key(Key::KEY_A), 1, 1),
+                (Event::Key(Key::KEY_A), 0, 0)
+            ]
+        );
+    }
+
+    #[test]
+    fn layout_memory_round_trips_and_skips_stale_saves() {
+        let dir = std::env::temp_dir().join(format!("makima-layouts-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let path = dir.join("makima/layouts.json");
+        let path = path.to_str().unwrap();
+        let layout_memory = HashMap::from([
+            (Client::Default, 2),
+            (Client::Class("firefox".to_string()), 1),
+        ]);
+        save_layout_memory(path, "Round Trip Keyboard", &layout_memory, 2);
+        save_layout_memory(path, "Round Trip Mouse", &HashMap::new(), 1);
+        assert_eq!(
+            load_layout_memory(path, "Round Trip Keyboard"),
+            layout_memory
+        );
+        save_layout_memory(
+            path,
+            "Round Trip Keyboard",
+            &HashMap::from([(Client::Default, 0)]),
+            1,
+        );
+        assert_eq!(
+            load_layout_memory(path, "Round Trip Keyboard"),
+            layout_memory
+        );
+        assert_eq!(load_layout_memory(path, "Round Trip Mouse"), HashMap::new());
+        assert_eq!(load_layout_memory(path, "Unknown"), HashMap::new());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn stopping_macros_ends_infinite_macros_and_returns_held_keys() {
+        let held_keys: Arc<Mutex<Vec<Key>>> = Arc::new(Mutex::new(Vec::new()));
+        let task = tokio::spawn({
+            let held_keys = held_keys.clone();
+            async move {
+                loop {
+                    held_keys.lock().await.push(Key::KEY_A);
+                    tokio::time::sleep(Duration::from_millis(1)).await;
+                    held_keys.lock().await.retain(|key| *key != Key::KEY_A);
+                }
+            }
+        });
+        while held_keys.lock().await.is_empty() {
+            tokio::task::yield_now().await;
+        }
+        let macros = Mutex::new(HashMap::from([(
+            Event::Key(Key::KEY_F1),
+            MacroTask {
+                task,
+                held_keys: held_keys.clone(),
+            },
+        )]));
+        assert_eq!(stop_macro_tasks(&macros).await, vec![Key::KEY_A]);
+        assert!(macros.lock().await.is_empty());
+        assert!(held_keys.lock().await.is_empty());
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        assert!(held_keys.lock().await.is_empty());
+        assert_eq!(Arc::strong_count(&held_keys), 1);
+    }
+
+    fn tapped_sticky_state(now: Instant) -> StickyState {
+        let mut state = StickyState::new();
+        assert!(state.release(now, 200, 1000));
+        state
+    }
+
+    #[test]
+    fn sticky_modifier_is_released_by_the_next_key() {
+        let now = Instant::now();
+        let mut state = tapped_sticky_state(now);
+        assert!(!state.locked);
+        assert!(!state.consume());
+    }
+
+    #[test]
+    fn sticky_modifier_held_while_typing_is_used_up() {
+        let now = Instant::now();
+        let mut state = StickyState::new();
+        assert!(state.consume());
+        assert!(!state.release(now, 200, 1000));
+    }
+
+    #[test]
+    fn sticky_modifier_locks_on_double_tap_and_unlocks_on_third_press() {
+        let now = Instant::now();
+        let mut state = tapped_sticky_state(now);
+        state.press(now + Duration::from_millis(100));
+        assert!(state.locked);
+        assert!(state.release(now + Duration::from_millis(150), 200, 1000));
+        assert_eq!(state.deadline, None);
+        assert!(state.consume());
+        assert!(state.consume());
+        assert!(!state.expire(now + Duration::from_secs(10)));
+        state.press(now + Duration::from_secs(10));
+        assert!(!state.release(now + Duration::from_secs(10), 200, 1000));
+    }
+
+    #[test]
+    fn sticky_modifier_is_cancelled_by_pressing_it_again() {
+        let now = Instant::now();
+        let mut state = tapped_sticky_state(now);
+        state.press(now + Duration::from_millis(300));
+        assert!(!state.locked);
+        assert!(!state.release(now + Duration::from_millis(350), 200, 1000));
+    }
+
+    #[test]
+    fn sticky_modifier_expires_after_the_sticky_timeout() {
+        let now = Instant::now();
+        let state = tapped_sticky_state(now);
+        assert!(!state.expire(now + Duration::from_millis(999)));
+        assert!(state.expire(now + Duration::from_millis(1000)));
+        let mut state = StickyState::new();
+        assert!(state.release(now, 200, 0));
+        assert!(!state.expire(now + Duration::from_secs(3600)));
+    }
+
+    #[test]
+    fn toggles_are_released_by_the_next_press_of_their_trigger() {
+        let mut toggled = vec![
+            (Event::Key(Key::KEY_F1), Toggle::Keys(vec![Key::KEY_W])),
+            (
+                Event::Key(Key::KEY_F2),
+                Toggle::Movement(Relative::Cursor(Cursor::CURSOR_UP)),
+            ),
+        ];
+        assert!(take_toggle(&mut toggled, Event::Key(Key::KEY_F3)).is_none());
+        assert!(matches!(
+            take_toggle(&mut toggled, Event::Key(Key::KEY_F1)),
+            Some(Toggle::Keys(keys)) if keys == vec![Key::KEY_W]
+        ));
+        assert!(take_toggle(&mut toggled, Event::Key(Key::KEY_F1)).is_none());
+        assert_eq!(toggled.len(), 1);
+        assert!(matches!(
+            take_toggle(&mut toggled, Event::Key(Key::KEY_F2)),
+            Some(Toggle::Movement(_))
+        ));
+        assert!(toggled.is_empty());
+    }
+}
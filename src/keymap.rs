@@ -0,0 +1,133 @@
+use evdev::Key;
+use std::collections::HashMap;
+use xkbcommon::xkb;
+
+const EVDEV_OFFSET: u32 = 8;
+
+pub struct TextKeymap {
+    keys: HashMap<char, Vec<Key>>,
+    unicode_input: bool,
+}
+
+impl TextKeymap {
+    pub fn new(keymap_file: Option<&String>, unicode_input: bool) -> Self {
+        let context = xkb::Context::new(xkb::CONTEXT_NO_FLAGS);
+        let keymap = match keymap_file {
+            Some(file) => std::fs::read_to_string(file).ok().and_then(|keymap| {
+                xkb::Keymap::new_from_string(
+                    &context,
+                    keymap,
+                    xkb::KEYMAP_FORMAT_TEXT_V1,
+                    xkb::KEYMAP_COMPILE_NO_FLAGS,
+                )
+            }),
+            None => xkb::Keymap::new_from_names(
+                &context,
+                "",
+                "",
+                "",
+                "",
+                None,
+                xkb::KEYMAP_COMPILE_NO_FLAGS,
+            ),
+        };
+        let mut keys: HashMap<char, Vec<Key>> = HashMap::new();
+        match keymap {
+            Some(keymap) => {
+                let levels: [&[Key]; 4] = [
+                    &[],
+                    &[Key::KEY_LEFTSHIFT],
+                    &[Key::KEY_RIGHTALT],
+                    &[Key::KEY_LEFTSHIFT, Key::KEY_RIGHTALT],
+                ];
+                for modifiers in levels {
+                    let mut state = xkb::State::new(&keymap);
+                    for modifier in modifiers {
+                        state.update_key(
+                            (modifier.code() as u32 + EVDEV_OFFSET).into(),
+                            xkb::KeyDirection::Down,
+                        );
+                    }
+                    let (min, max) = (keymap.min_keycode().raw(), keymap.max_keycode().raw());
+                    for keycode in min.max(EVDEV_OFFSET)..=max {
+                        let Some(character) = char::from_u32(state.key_get_utf32(keycode.into()))
+                        else {
+                            continue;
+                        };
+                        if character == '\0' || modifiers.contains(&key_from_xkb(keycode)) {
+                            continue;
+                        }
+                        let mut chord = modifiers.to_vec();
+                        chord.push(key_from_xkb(keycode));
+                        keys.entry(character).or_insert(chord);
+                    }
+                }
+                if let Some(chord) = keys.get(&'\r').cloned() {
+                    keys.insert('\n', chord);
+                }
+            }
+            None => println!(
+                "Warning: unable to compile the XKB keymap, text will only be typed through the Unicode input method.\n"
+            ),
+        }
+        Self {
+            keys,
+            unicode_input,
+        }
+    }
+
+    pub fn get_chords(&self, text: &str) -> Vec<Vec<Key>> {
+        let mut chords: Vec<Vec<Key>> = Vec::new();
+        for character in text.chars() {
+            if let Some(chord) = self.keys.get(&character) {
+                chords.push(chord.clone());
+            } else if self.unicode_input {
+                let u = self
+                    .keys
+                    .get(&'u')
+                    .map_or(Key::KEY_U, |chord| *chord.last().unwrap());
+                chords.push(vec![Key::KEY_LEFTCTRL, Key::KEY_LEFTSHIFT, u]);
+                for digit in format!("{:x}", character as u32).chars() {
+                    chords.push(
+                        self.keys
+                            .get(&digit)
+                            .cloned()
+                            .unwrap_or_else(|| vec![default_hex_key(digit)]),
+                    );
+                }
+                chords.push(vec![Key::KEY_SPACE]);
+            } else {
+                println!(
+                    "Warning: the keymap can't type {:?} and UNICODE_INPUT is disabled, skipping it.\n",
+                    character
+                );
+            }
+        }
+        chords
+    }
+}
+
+fn key_from_xkb(keycode: u32) -> Key {
+    Key::new((keycode - EVDEV_OFFSET) as u16)
+}
+
+fn default_hex_key(digit: char) -> Key {
+    match digit {
+        '0' => Key::KEY_0,
+        '1' => Key::KEY_1,
+        '2' => Key::KEY_2,
+        '3' => Key::KEY_3,
+        '4' => Key::KEY_4,
+        '5' => Key::KEY_5,
+        '6' => Key::KEY_6,
+        '7' => Key::KEY_7,
+        '8' => Key::KEY_8,
+        '9' => Key::KEY_9,
+        'a' => Key::KEY_A,
+        'b' => Key::KEY_B,
+        'c' => Key::KEY_C,
+        'd' => Key::KEY_D,
+        'e' => Key::KEY_E,
+        _ => Key::KEY_F,
+    }
+}
@@ -0,0 +1,15 @@
+use std::{env, process::Command};
+
+pub fn get_socket_path() -> String {
+    let runtime_dir = match env::var("XDG_RUNTIME_DIR") {
+        Ok(runtime_dir) => runtime_dir,
+        Err(_) => {
+            let uid = Command::new("sh").arg("-c").arg("id -u").output().unwrap();
+            match std::str::from_utf8(uid.stdout.as_slice()).unwrap().trim() {
+                "0" => "/run".to_string(),
+                uid => format!("/run/user/{}", uid),
+            }
+        }
+    };
+    format!("{}/makima.sock", runtime_dir.trim_end_matches("/"))
+}
@@ -0,0 +1,517 @@
+use crate::config::Axis;
+use evdev::{AbsoluteAxisType, EventType, InputEvent, Key};
+use std::time::{Duration, SystemTime};
+
+const TAP_TIMEOUT: Duration = Duration::from_millis(200);
+
+#[derive(Clone)]
+pub struct TouchpadSettings {
+    pub mouse: bool,
+    pub speed: f64,
+    pub scroll_speed: f64,
+    pub natural_scroll: bool,
+    pub tap_to_click: bool,
+}
+
+pub enum TouchpadAction {
+    Move(i32, i32),
+    Scroll(i32, i32),
+    Button(Key, i32),
+    Gesture(Axis),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Edge {
+    Left,
+    Right,
+    Top,
+    Bottom,
+}
+
+#[derive(Clone, Copy, Default)]
+struct Touch {
+    active: bool,
+    position: (i32, i32),
+    previous: Option<(i32, i32)>,
+}
+
+pub struct Touchpad {
+    settings: TouchpadSettings,
+    touches: Vec<Touch>,
+    slot: usize,
+    bounds: ((i32, i32), (i32, i32)),
+    tap_distance: f64,
+    swipe_distance: f64,
+    touch_start: Option<SystemTime>,
+    fingers: usize,
+    max_fingers: usize,
+    travel: f64,
+    edge: Option<Edge>,
+    gesture_start: Option<((f64, f64), f64)>,
+    gesture_fired: bool,
+    clicked: Option<Key>,
+    was_clicked: bool,
+    remainder: (f64, f64),
+}
+
+impl Touchpad {
+    pub fn new(settings: TouchpadSettings, device: &evdev::Device) -> Self {
+        let bounds = match device.get_abs_state() {
+            Ok(abs_state) => {
+                let x = abs_state[AbsoluteAxisType::ABS_MT_POSITION_X.0 as usize];
+                let y = abs_state[AbsoluteAxisType::ABS_MT_POSITION_Y.0 as usize];
+                ((x.minimum, x.maximum), (y.minimum, y.maximum))
+            }
+            Err(_) => ((0, 1000), (0, 1000)),
+        };
+        Self::with_bounds(settings, bounds)
+    }
+
+    fn with_bounds(settings: TouchpadSettings, bounds: ((i32, i32), (i32, i32))) -> Self {
+        let width = (bounds.0 .1 - bounds.0 .0).max(1) as f64;
+        Self {
+            settings,
+            touches: vec![Touch::default()],
+            slot: 0,
+            bounds,
+            tap_distance: width / 40.0,
+            swipe_distance: width / 6.0,
+            touch_start: None,
+            fingers: 0,
+            max_fingers: 0,
+            travel: 0.0,
+            edge: None,
+            gesture_start: None,
+            gesture_fired: false,
+            clicked: None,
+            was_clicked: false,
+            remainder: (0.0, 0.0),
+        }
+    }
+
+    pub fn handle(&mut self, event: &InputEvent) -> Option<Vec<TouchpadAction>> {
+        let mut actions: Vec<TouchpadAction> = Vec::new();
+        match event.event_type() {
+            EventType::ABSOLUTE => match AbsoluteAxisType(event.code()) {
+                AbsoluteAxisType::ABS_MT_SLOT => {
+                    self.slot = event.value().max(0) as usize;
+                    if self.slot >= self.touches.len() {
+                        self.touches.resize(self.slot + 1, Touch::default());
+                    }
+                }
+                // The kernel doesn't repeat coordinates that didn't change since the slot's
+                // last contact, so a new contact starts from the previous position.
+                AbsoluteAxisType::ABS_MT_TRACKING_ID => {
+                    let touch = &mut self.touches[self.slot];
+                    touch.active = event.value() != -1;
+                    touch.previous = None;
+                }
+                AbsoluteAxisType::ABS_MT_POSITION_X => {
+                    self.touches[self.slot].position.0 = event.value()
+                }
+                AbsoluteAxisType::ABS_MT_POSITION_Y => {
+                    self.touches[self.slot].position.1 = event.value()
+                }
+                _ => {}
+            },
+            EventType::KEY => match Key(event.code()) {
+                Key::BTN_LEFT if !self.settings.mouse => {}
+                Key::BTN_LEFT if event.value() == 1 => {
+                    let button = match self.touches.iter().filter(|touch| touch.active).count() {
+                        0 | 1 => Key::BTN_LEFT,
+                        2 => Key::BTN_RIGHT,
+                        _ => Key::BTN_MIDDLE,
+                    };
+                    self.clicked = Some(button);
+                    self.was_clicked = true;
+                    actions.push(TouchpadAction::Button(button, 1));
+                }
+                Key::BTN_LEFT => {
+                    if let Some(button) = self.clicked.take() {
+                        actions.push(TouchpadAction::Button(button, 0));
+                    }
+                }
+                Key::BTN_TOUCH
+                | Key::BTN_TOOL_FINGER
+                | Key::BTN_TOOL_DOUBLETAP
+                | Key::BTN_TOOL_TRIPLETAP
+                | Key::BTN_TOOL_QUADTAP
+                | Key::BTN_TOOL_QUINTTAP => {}
+                _ => return None,
+            },
+            EventType::SYNCHRONIZATION => self.end_frame(event.timestamp(), &mut actions),
+            _ => return None,
+        }
+        Some(actions)
+    }
+
+    fn end_frame(&mut self, timestamp: SystemTime, actions: &mut Vec<TouchpadAction>) {
+        let fingers = self.touches.iter().filter(|touch| touch.active).count();
+        if fingers == 0 {
+            if let Some(touch_start) = self.touch_start.take() {
+                let is_tap = timestamp.duration_since(touch_start).unwrap_or_default()
+                    < TAP_TIMEOUT
+                    && self.travel < self.tap_distance;
+                if self.settings.mouse && self.settings.tap_to_click && is_tap && !self.was_clicked
+                {
+                    let button = match self.max_fingers {
+                        1 => Key::BTN_LEFT,
+                        2 => Key::BTN_RIGHT,
+                        _ => Key::BTN_MIDDLE,
+                    };
+                    actions.push(TouchpadAction::Button(button, 1));
+                    actions.push(TouchpadAction::Button(button, 0));
+                }
+            }
+            self.fingers = 0;
+            return;
+        }
+        if self.touch_start.is_none() {
+            self.touch_start = Some(timestamp);
+            self.max_fingers = 0;
+            self.travel = 0.0;
+            self.gesture_fired = false;
+            self.was_clicked = self.clicked.is_some();
+            self.edge = match fingers {
+                1 => self.get_edge(self.get_centroid().0),
+                _ => None,
+            };
+        }
+        if fingers != self.fingers {
+            self.fingers = fingers;
+            self.max_fingers = self.max_fingers.max(fingers);
+            self.gesture_start = None;
+            self.remainder = (0.0, 0.0);
+        }
+        let deltas: Vec<(f64, f64)> = self
+            .touches
+            .iter()
+            .filter(|touch| touch.active)
+            .filter_map(|touch| {
+                let previous = touch.previous?;
+                Some((
+                    (touch.position.0 - previous.0) as f64,
+                    (touch.position.1 - previous.1) as f64,
+                ))
+            })
+            .collect();
+        for touch in self.touches.iter_mut().filter(|touch| touch.active) {
+            touch.previous = Some(touch.position);
+        }
+        if !self.gesture_fired {
+            if let Some(gesture) = self.detect_gesture() {
+                self.gesture_fired = true;
+                actions.push(TouchpadAction::Gesture(gesture));
+            }
+        }
+        if deltas.is_empty() || !self.settings.mouse {
+            return;
+        }
+        let delta = (
+            deltas.iter().map(|delta| delta.0).sum::<f64>() / deltas.len() as f64,
+            deltas.iter().map(|delta| delta.1).sum::<f64>() / deltas.len() as f64,
+        );
+        self.travel += f64::hypot(delta.0, delta.1);
+        let movement = match (fingers, self.max_fingers, self.gesture_fired) {
+            (1, 1, _) => (delta.0 * self.settings.speed, delta.1 * self.settings.speed),
+            (2, 2, false) if self.settings.natural_scroll => (
+                -delta.0 * self.settings.scroll_speed,
+                delta.1 * self.settings.scroll_speed,
+            ),
+            (2, 2, false) => (
+                delta.0 * self.settings.scroll_speed,
+                -delta.1 * self.settings.scroll_speed,
+            ),
+            _ => return,
+        };
+        self.remainder = (self.remainder.0 + movement.0, self.remainder.1 + movement.1);
+        let (x, y) = (self.remainder.0.trunc(), self.remainder.1.trunc());
+        self.remainder = (self.remainder.0 - x, self.remainder.1 - y);
+        if x == 0.0 && y == 0.0 {
+            return;
+        }
+        if fingers == 1 {
+            actions.push(TouchpadAction::Move(x as i32, y as i32));
+        } else {
+            actions.push(TouchpadAction::Scroll(x as i32, y as i32));
+        }
+    }
+
+    fn detect_gesture(&mut self) -> Option<Axis> {
+        let (centroid, spread) = self.get_centroid();
+        let (start, start_spread) = *self.gesture_start.get_or_insert((centroid, spread));
+        let travel = (centroid.0 - start.0, centroid.1 - start.1);
+        let pinch = spread - start_spread;
+        match self.fingers {
+            1 => match self.edge? {
+                Edge::Left if travel.0 > self.swipe_distance => Some(Axis::EDGE_SWIPE_LEFT),
+                Edge::Right if -travel.0 > self.swipe_distance => Some(Axis::EDGE_SWIPE_RIGHT),
+                Edge::Top if travel.1 > self.swipe_distance => Some(Axis::EDGE_SWIPE_TOP),
+                Edge::Bottom if -travel.1 > self.swipe_distance => Some(Axis::EDGE_SWIPE_BOTTOM),
+                _ => None,
+            },
+            2 if pinch.abs() > self.swipe_distance / 2.0
+                && pinch.abs() > f64::hypot(travel.0, travel.1) =>
+            {
+                if pinch < 0.0 {
+                    Some(Axis::PINCH_IN)
+                } else {
+                    Some(Axis::PINCH_OUT)
+                }
+            }
+            fingers @ (3 | 4) if f64::hypot(travel.0, travel.1) > self.swipe_distance => {
+                let direction = if travel.0.abs() > travel.1.abs() {
+                    if travel.0 < 0.0 {
+                        0
+                    } else {
+                        1
+                    }
+                } else if travel.1 < 0.0 {
+                    2
+                } else {
+                    3
+                };
+                let swipes = match fingers {
+                    3 => [
+                        Axis::SWIPE_3_LEFT,
+                        Axis::SWIPE_3_RIGHT,
+                        Axis::SWIPE_3_UP,
+                        Axis::SWIPE_3_DOWN,
+                    ],
+                    _ => [
+                        Axis::SWIPE_4_LEFT,
+                        Axis::SWIPE_4_RIGHT,
+                        Axis::SWIPE_4_UP,
+                        Axis::SWIPE_4_DOWN,
+                    ],
+                };
+                Some(swipes[direction])
+            }
+            _ => None,
+        }
+    }
+
+    fn get_centroid(&self) -> ((f64, f64), f64) {
+        let positions: Vec<(f64, f64)> = self
+            .touches
+            .iter()
+            .filter(|touch| touch.active)
+            .map(|touch| (touch.position.0 as f64, touch.position.1 as f64))
+            .collect();
+        let count = positions.len().max(1) as f64;
+        let centroid = (
+            positions.iter().map(|position| position.0).sum::<f64>() / count,
+            positions.iter().map(|position| position.1).sum::<f64>() / count,
+        );
+        let spread = positions
+            .iter()
+            .map(|position| f64::hypot(position.0 - centroid.0, position.1 - centroid.1))
+            .sum::<f64>()
+            / count;
+        (centroid, spread)
+    }
+
+    fn get_edge(&self, position: (f64, f64)) -> Option<Edge> {
+        let ((min_x, max_x), (min_y, max_y)) = self.bounds;
+        let margin = (max_x - min_x) as f64 / 20.0;
+        if position.0 < min_x as f64 + margin {
+            Some(Edge::Left)
+        } else if position.0 > max_x as f64 - margin {
+            Some(Edge::Right)
+        } else if position.1 < min_y as f64 + margin {
+            Some(Edge::Top)
+        } else if position.1 > max_y as f64 - margin {
+            Some(Edge::Bottom)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_touchpad(natural_scroll: bool) -> Touchpad {
+        let settings = TouchpadSettings {
+            mouse: true,
+            speed: 1.0,
+            scroll_speed: 0.5,
+            natural_scroll,
+            tap_to_click: true,
+        };
+        Touchpad::with_bounds(settings, ((0, 1000), (0, 1000)))
+    }
+
+    fn event(event_type: EventType, code: u16, value: i32, ms: u64) -> InputEvent {
+        InputEvent::from(libc::input_event {
+            time: libc::timeval {
+                tv_sec: (ms / 1000) as libc::time_t,
+                tv_usec: (ms % 1000 * 1000) as libc::suseconds_t,
+            },
+            type_: event_type.0,
+            code,
+            value,
+        })
+    }
+
+    // Sends one frame with the given fingers, each as (slot, position), or lifts all of
+    // them if the list is empty, and returns the resulting actions.
+    fn frame(
+        touchpad: &mut Touchpad,
+        fingers: &[(i32, (i32, i32))],
+        ms: u64,
+    ) -> Vec<TouchpadAction> {
+        let abs =
+            |code: AbsoluteAxisType, value: i32| event(EventType::ABSOLUTE, code.0, value, ms);
+        let mut events = Vec::new();
+        for slot in 0..touchpad.touches.len().max(fingers.len()) as i32 {
+            let finger = fingers.iter().find(|(finger, _)| *finger == slot);
+            events.push(abs(AbsoluteAxisType::ABS_MT_SLOT, slot));
+            match finger {
+                Some((_, (x, y))) => {
+                    if !touchpad
+                        .touches
+                        .get(slot as usize)
+                        .is_some_and(|touch| touch.active)
+                    {
+                        events.push(abs(AbsoluteAxisType::ABS_MT_TRACKING_ID, slot));
+                    }
+                    events.push(abs(AbsoluteAxisType::ABS_MT_POSITION_X, *x));
+                    events.push(abs(AbsoluteAxisType::ABS_MT_POSITION_Y, *y));
+                }
+                None => events.push(abs(AbsoluteAxisType::ABS_MT_TRACKING_ID, -1)),
+            }
+        }
+        events.push(event(EventType::SYNCHRONIZATION, 0, 0, ms));
+        events
+            .iter()
+            .flat_map(|event| touchpad.handle(event).unwrap())
+            .collect()
+    }
+
+    fn buttons(actions: &[TouchpadAction]) -> Vec<(Key, i32)> {
+        actions
+            .iter()
+            .filter_map(|action| match action {
+                TouchpadAction::Button(key, value) => Some((*key, *value)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn taps_click_with_the_number_of_fingers() {
+        let mut touchpad = new_touchpad(false);
+        frame(&mut touchpad, &[(0, (500, 500))], 1000);
+        let actions = frame(&mut touchpad, &[], 1050);
+        assert_eq!(
+            buttons(&actions),
+            vec![(Key::BTN_LEFT, 1), (Key::BTN_LEFT, 0)]
+        );
+        frame(&mut touchpad, &[(0, (400, 500)), (1, (600, 500))], 2000);
+        let actions = frame(&mut touchpad, &[], 2050);
+        assert_eq!(
+            buttons(&actions),
+            vec![(Key::BTN_RIGHT, 1), (Key::BTN_RIGHT, 0)]
+        );
+    }
+
+    #[test]
+    fn long_touches_and_movements_are_not_taps() {
+        let mut touchpad = new_touchpad(false);
+        frame(&mut touchpad, &[(0, (500, 500))], 1000);
+        assert!(buttons(&frame(&mut touchpad, &[], 1300)).is_empty());
+        frame(&mut touchpad, &[(0, (500, 500))], 2000);
+        frame(&mut touchpad, &[(0, (600, 500))], 2020);
+        assert!(buttons(&frame(&mut touchpad, &[], 2040)).is_empty());
+    }
+
+    #[test]
+    fn one_finger_moves_the_cursor() {
+        let mut touchpad = new_touchpad(false);
+        frame(&mut touchpad, &[(0, (500, 500))], 1000);
+        let actions = frame(&mut touchpad, &[(0, (510, 495))], 1010);
+        assert!(matches!(actions[..], [TouchpadAction::Move(10, -5)]));
+    }
+
+    #[test]
+    fn two_fingers_scroll() {
+        let mut touchpad = new_touchpad(false);
+        frame(&mut touchpad, &[(0, (400, 500)), (1, (600, 500))], 1000);
+        let actions = frame(&mut touchpad, &[(0, (400, 520)), (1, (600, 520))], 1010);
+        assert!(matches!(actions[..], [TouchpadAction::Scroll(0, -10)]));
+        let mut touchpad = new_touchpad(true);
+        frame(&mut touchpad, &[(0, (400, 500)), (1, (600, 500))], 1000);
+        let actions = frame(&mut touchpad, &[(0, (400, 520)), (1, (600, 520))], 1010);
+        assert!(matches!(actions[..], [TouchpadAction::Scroll(0, 10)]));
+    }
+
+    #[test]
+    fn swipes_and_edge_swipes_fire_gestures_once() {
+        let mut touchpad = new_touchpad(false);
+        let fingers = |x: i32| [(0, (x, 400)), (1, (x, 500)), (2, (x, 600))];
+        frame(&mut touchpad, &fingers(300), 1000);
+        let actions = frame(&mut touchpad, &fingers(500), 1050);
+        assert!(matches!(
+            actions[..],
+            [TouchpadAction::Gesture(Axis::SWIPE_3_RIGHT)]
+        ));
+        assert!(frame(&mut touchpad, &fingers(700), 1100).is_empty());
+        frame(&mut touchpad, &[], 1150);
+        frame(&mut touchpad, &[(0, (10, 500))], 2000);
+        let actions = frame(&mut touchpad, &[(0, (300, 500))], 2050);
+        assert!(actions
+            .iter()
+            .any(|action| matches!(action, TouchpadAction::Gesture(Axis::EDGE_SWIPE_LEFT))));
+    }
+
+    #[test]
+    fn clicks_depend_on_the_fingers_on_the_touchpad() {
+        let mut touchpad = new_touchpad(false);
+        frame(&mut touchpad, &[(0, (400, 500)), (1, (600, 500))], 1000);
+        let press = touchpad
+            .handle(&event(EventType::KEY, Key::BTN_LEFT.code(), 1, 1010))
+            .unwrap();
+        let release = touchpad
+            .handle(&event(EventType::KEY, Key::BTN_LEFT.code(), 0, 1020))
+            .unwrap();
+        assert_eq!(buttons(&press), vec![(Key::BTN_RIGHT, 1)]);
+        assert_eq!(buttons(&release), vec![(Key::BTN_RIGHT, 0)]);
+        assert!(buttons(&frame(&mut touchpad, &[], 1030)).is_empty());
+    }
+
+    #[test]
+    fn new_contacts_keep_coordinates_the_kernel_does_not_repeat() {
+        let mut touchpad = new_touchpad(false);
+        frame(&mut touchpad, &[(0, (500, 500))], 1000);
+        frame(&mut touchpad, &[], 1300);
+        let abs = |code: AbsoluteAxisType, value: i32, ms: u64| {
+            event(EventType::ABSOLUTE, code.0, value, ms)
+        };
+        let mut actions = Vec::new();
+        for event in [
+            abs(AbsoluteAxisType::ABS_MT_SLOT, 0, 2000),
+            abs(AbsoluteAxisType::ABS_MT_TRACKING_ID, 1, 2000),
+            abs(AbsoluteAxisType::ABS_MT_POSITION_X, 520, 2000),
+            event(EventType::SYNCHRONIZATION, 0, 0, 2000),
+            abs(AbsoluteAxisType::ABS_MT_POSITION_X, 530, 2010),
+            event(EventType::SYNCHRONIZATION, 0, 0, 2010),
+        ] {
+            actions.extend(touchpad.handle(&event).unwrap());
+        }
+        assert_eq!(touchpad.touches[0].position, (530, 500));
+        assert!(matches!(actions[..], [TouchpadAction::Move(10, 0)]));
+    }
+
+    #[test]
+    fn other_events_are_not_handled() {
+        let mut touchpad = new_touchpad(false);
+        assert!(touchpad
+            .handle(&event(EventType::KEY, Key::BTN_SOUTH.code(), 1, 0))
+            .is_none());
+        assert!(touchpad
+            .handle(&event(EventType::RELATIVE, 0, 1, 0))
+            .is_none());
+    }
+}
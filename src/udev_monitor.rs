@@ -1,9 +1,17 @@
+use crate::active_client::start_window_watcher;
+use crate::check::{find_layout_name_problems, find_problems};
+use crate::config::{is_associated, is_config_file, parse_associations};
 use crate::config::{Associations, Event};
-use crate::event_reader::EventReader;
-use crate::virtual_devices::VirtualDevices;
+use crate::control::{start_control_server, ControlMessage, ControlRequest};
+use crate::event_reader::{EventReader, StickyModifier};
+use crate::virtual_devices::{KeyboardLeds, VirtualDevices, VirtualGamepad};
 use crate::Config;
-use evdev::{Device, EventStream};
-use std::{env, path::Path, process::Command, sync::Arc};
+use evdev::{Device, EventStream, PropType};
+use inotify::{Inotify, WatchMask};
+use std::{
+    collections::HashMap, env, ffi::OsString, path::Path, process::Command, sync::Arc,
+    time::Duration,
+};
 use tokio::sync::Mutex;
 use tokio::task::JoinHandle;
 use tokio_stream::StreamExt;
@@ -22,6 +30,12 @@ pub enum Server {
     Failed,
 }
 
+pub struct ReaderTask {
+    pub name: String,
+    pub reader: Arc<EventReader>,
+    pub task: JoinHandle<()>,
+}
+
 #[derive(Clone)]
 pub struct Environment {
     pub user: Result<String, env::VarError>,
@@ -29,9 +43,33 @@ pub struct Environment {
     pub server: Server,
 }
 
-pub async fn start_monitoring_udev(config_files: Vec<Config>, mut tasks: Vec<JoinHandle<()>>) {
+#[derive(Clone)]
+pub struct SharedState {
+    pub environment: Environment,
+    pub active_window: Arc<Mutex<Client>>,
+    pub modifiers: Arc<Mutex<Vec<Event>>>,
+    pub modifier_was_activated: Arc<Mutex<bool>>,
+    pub sticky_modifiers: Arc<Mutex<Vec<StickyModifier>>>,
+    pub keyboard_leds: Arc<Mutex<KeyboardLeds>>,
+    pub gamepad: Arc<Mutex<Option<VirtualGamepad>>>,
+}
+
+pub async fn start_monitoring_udev(
+    config_path: String,
+    mut config_files: Vec<Config>,
+    mut tasks: HashMap<String, ReaderTask>,
+) {
     let environment = set_environment();
-    launch_tasks(&config_files, &mut tasks, environment.clone());
+    let mut shared_state = SharedState {
+        active_window: start_window_watcher(&environment),
+        environment,
+        modifiers: Arc::new(Mutex::new(Default::default())),
+        modifier_was_activated: Arc::new(Mutex::new(true)),
+        sticky_modifiers: Arc::new(Mutex::new(Vec::new())),
+        keyboard_leds: Arc::new(Mutex::new(Default::default())),
+        gamepad: Arc::new(Mutex::new(None)),
+    };
+    launch_tasks(&config_files, &mut tasks, &shared_state);
     let mut monitor = tokio_udev::AsyncMonitorSocket::new(
         tokio_udev::MonitorBuilder::new()
             .unwrap()
@@ -41,25 +79,330 @@ pub async fn start_monitoring_udev(config_files: Vec<Config>, mut tasks: Vec<Joi
             .unwrap(),
     )
     .unwrap();
-    while let Some(Ok(event)) = monitor.next().await {
-        if is_mapped(&event.device(), &config_files) {
-            println!("---------------------\n\nReinitializing...\n");
-            for task in &tasks {
-                task.abort();
+    let mut config_events = watch_config_dir(&config_path);
+    let mut control_requests = start_control_server();
+    loop {
+        let (changed_files, reply) = tokio::select! {
+            Some(Ok(event)) = monitor.next() => {
+                if is_mapped(&event.device(), &config_files) {
+                    println!("---------------------\n\nReinitializing...\n");
+                    for (_, reader_task) in tasks.drain() {
+                        reader_task.task.abort();
+                        let _ = reader_task.task.await;
+                        reader_task.reader.stop_macros().await;
+                    }
+                    shared_state.modifiers = Arc::new(Mutex::new(Default::default()));
+                    shared_state.modifier_was_activated = Arc::new(Mutex::new(true));
+                    shared_state.sticky_modifiers = Arc::new(Mutex::new(Vec::new()));
+                    launch_tasks(&config_files, &mut tasks, &shared_state)
+                }
+                continue;
+            }
+            Some(event) = next_config_event(&mut config_events) => {
+                let mut changed_files: Vec<OsString> = event.name.into_iter().collect();
+                while let Ok(Some(event)) = tokio::time::timeout(
+                    Duration::from_millis(200),
+                    next_config_event(&mut config_events),
+                )
+                .await
+                {
+                    changed_files.extend(event.name);
+                }
+                (changed_files, None)
             }
-            tasks.clear();
-            launch_tasks(&config_files, &mut tasks, environment.clone())
+            Some((request, reply)) = next_control_request(&mut control_requests) => {
+                if let ControlRequest::Reload = request {
+                    let mut changed_files: Vec<OsString> = config_files
+                        .iter()
+                        .map(|config| OsString::from(format!("{}.toml", config.name)))
+                        .collect();
+                    if let Ok(config_dir) = std::fs::read_dir(&config_path) {
+                        changed_files.extend(config_dir.filter_map(|file| Some(file.ok()?.file_name())));
+                    }
+                    (changed_files, Some(reply))
+                } else {
+                    let _ = reply.send(handle_control_request(request, &tasks).await);
+                    continue;
+                }
+            }
+            else => break,
+        };
+        let previous_config_files = config_files.clone();
+        let changed_devices = reload_config_files(&config_path, &mut config_files, changed_files);
+        if !changed_devices.is_empty() {
+            println!("---------------------\n\nReloading config files...\n");
+            let is_affected = |config_files: &Vec<Config>, device: &Device| {
+                config_files.iter().any(|config| {
+                    changed_devices
+                        .contains(&config.name.split("::").collect::<Vec<&str>>()[0].to_string())
+                        && is_associated(config, config_files, device)
+                })
+            };
+            for (event_device, device) in evdev::enumerate() {
+                if is_affected(&previous_config_files, &device)
+                    || is_affected(&config_files, &device)
+                {
+                    if let Some(reader_task) =
+                        tasks.remove(event_device.to_str().unwrap_or_default())
+                    {
+                        reader_task.task.abort();
+                        let _ = reader_task.task.await;
+                        reader_task.reader.stop_macros().await;
+                    }
+                }
+            }
+            launch_tasks(&config_files, &mut tasks, &shared_state)
+        }
+        if let Some(reply) = reply {
+            let _ = reply.send(format!(
+                "Reloaded {} config files, {} readers running.\n",
+                config_files.len(),
+                tasks.len()
+            ));
+        }
+    }
+}
+
+async fn next_control_request(
+    control_requests: &mut Option<tokio::sync::mpsc::Receiver<ControlMessage>>,
+) -> Option<ControlMessage> {
+    match control_requests {
+        Some(receiver) => receiver.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+async fn handle_control_request(
+    request: ControlRequest,
+    tasks: &HashMap<String, ReaderTask>,
+) -> String {
+    let mut event_devices: Vec<&String> = tasks.keys().collect();
+    event_devices.sort();
+    let selected = |device: &Option<String>| -> Result<Vec<&String>, String> {
+        let selected: Vec<&String> = event_devices
+            .iter()
+            .filter(|event_device| match device {
+                Some(device) => {
+                    **event_device == device
+                        || event_device.trim_start_matches("/dev/input/") == device
+                        || &tasks[**event_device].name == device
+                }
+                None => true,
+            })
+            .copied()
+            .collect();
+        match (selected.is_empty(), device) {
+            (true, Some(device)) => Err(format!("Error: no device {:?} is being read.\n", device)),
+            (true, None) => Err("Error: no device is being read.\n".to_string()),
+            _ => Ok(selected),
+        }
+    };
+    match request {
+        ControlRequest::List => {
+            let mut reply = String::new();
+            for event_device in event_devices {
+                let reader_task = &tasks[event_device];
+                let (layout, client, paused) = reader_task.reader.get_state().await;
+                reply.push_str(&format!(
+                    "{} {:?}\n    configs: {}\n    layout: {}\n    client: {}\n    {}\n",
+                    event_device,
+                    reader_task.name,
+                    reader_task.reader.get_config_names().join(", "),
+                    layout,
+                    match client {
+                        Client::Default => "default".to_string(),
+                        Client::Class(class) => class,
+                    },
+                    if paused { "paused" } else { "running" }
+                ));
+            }
+            if reply.is_empty() {
+                reply.push_str("No devices are being read.\n");
+            }
+            reply
+        }
+        ControlRequest::Layout(layout, device) => {
+            let event_devices = match selected(&device) {
+                Ok(event_devices) => event_devices,
+                Err(error) => return error,
+            };
+            let mut reply = String::new();
+            for event_device in event_devices {
+                let reader = &tasks[event_device].reader;
+                match reader.set_layout(&layout).await {
+                    Ok(()) => reply.push_str(&format!(
+                        "{}: switched to layout {}.\n",
+                        event_device,
+                        reader.get_state().await.0
+                    )),
+                    Err(error) => reply.push_str(&format!("Error: {}: {}.\n", event_device, error)),
+                }
+            }
+            reply
+        }
+        ControlRequest::Pause(ref device) | ControlRequest::Resume(ref device) => {
+            let paused = matches!(request, ControlRequest::Pause(_));
+            let event_devices = match selected(device) {
+                Ok(event_devices) => event_devices,
+                Err(error) => return error,
+            };
+            let mut reply = String::new();
+            for event_device in event_devices {
+                tasks[event_device].reader.set_paused(paused).await;
+                reply.push_str(&format!(
+                    "{}: {}.\n",
+                    event_device,
+                    if paused { "paused" } else { "resumed" }
+                ));
+            }
+            reply
+        }
+        ControlRequest::Reload => String::new(),
+    }
+}
+
+fn watch_config_dir(config_path: &str) -> Option<inotify::EventStream<[u8; 1024]>> {
+    let inotify = match Inotify::init() {
+        Ok(inotify) => inotify,
+        Err(_) => {
+            println!("Warning: unable to initialize inotify, config files won't be reloaded automatically.\n");
+            return None;
+        }
+    };
+    if inotify
+        .watches()
+        .add(
+            config_path,
+            WatchMask::CLOSE_WRITE
+                | WatchMask::MOVED_TO
+                | WatchMask::MOVED_FROM
+                | WatchMask::DELETE,
+        )
+        .is_err()
+    {
+        println!(
+            "Warning: unable to watch {:?}, config files won't be reloaded automatically.\n",
+            config_path
+        );
+        return None;
+    }
+    inotify.into_event_stream([0; 1024]).ok()
+}
+
+async fn next_config_event(
+    config_events: &mut Option<inotify::EventStream<[u8; 1024]>>,
+) -> Option<inotify::EventOwned> {
+    match config_events {
+        Some(stream) => match stream.next().await {
+            Some(Ok(event)) => Some(event),
+            _ => None,
+        },
+        None => std::future::pending().await,
+    }
+}
+
+fn reload_config_files(
+    config_path: &str,
+    config_files: &mut Vec<Config>,
+    mut changed_files: Vec<OsString>,
+) -> Vec<String> {
+    let mut changed_devices: Vec<String> = Vec::new();
+    changed_files.sort();
+    changed_files.dedup();
+    for filename in changed_files {
+        let Some(filename) = filename.to_str() else {
+            continue;
+        };
+        if !is_config_file(filename) {
+            continue;
+        }
+        let name: String = filename.split(".toml").collect::<Vec<&str>>()[0].to_string();
+        let file = format!("{}/{}", config_path.trim_end_matches("/"), filename);
+        let new_config = if Path::new(&file).exists() {
+            match load_config_file(&file, filename, &name, config_files) {
+                Ok(config) => Some(config),
+                Err(error) => {
+                    println!(
+                        "Config file {:?} contains errors, keeping the previous version:\n{}\n",
+                        filename, error
+                    );
+                    continue;
+                }
+            }
+        } else {
+            println!("Config file {:?} removed.\n", filename);
+            None
+        };
+        config_files.retain(|config| config.name != name);
+        if let Some(config) = new_config {
+            config_files.push(config);
+        }
+        let device_name = name.split("::").collect::<Vec<&str>>()[0].to_string();
+        if !changed_devices.contains(&device_name) {
+            changed_devices.push(device_name);
         }
     }
+    changed_devices
+}
+
+pub fn load_config_files(config_path: &str) -> Vec<Config> {
+    let mut config_files: Vec<Config> = Vec::new();
+    let Ok(config_dir) = std::fs::read_dir(config_path) else {
+        return config_files;
+    };
+    let mut filenames: Vec<String> = config_dir
+        .filter_map(|file| file.ok()?.file_name().into_string().ok())
+        .filter(|filename| is_config_file(filename))
+        .collect();
+    filenames.sort();
+    for filename in filenames {
+        let name: String = filename.split(".toml").collect::<Vec<&str>>()[0].to_string();
+        let file = format!("{}/{}", config_path.trim_end_matches("/"), filename);
+        match load_config_file(&file, &filename, &name, &config_files) {
+            Ok(config) => config_files.push(config),
+            Err(error) => println!(
+                "Config file {:?} contains errors, ignoring it:\n{}\n",
+                filename, error
+            ),
+        }
+    }
+    config_files
+}
+
+fn load_config_file(
+    file: &str,
+    filename: &str,
+    name: &str,
+    config_files: &[Config],
+) -> Result<Config, String> {
+    let problems = match std::fs::read_to_string(file) {
+        Ok(content) => find_problems(filename, &content),
+        Err(error) => vec![format!("{}: unable to read file: {}", filename, error)],
+    };
+    if !problems.is_empty() {
+        return Err(problems.join("\n"));
+    }
+    let config = Config::new_from_file(file, name.to_string())?;
+    let layout_names: Vec<(String, Option<String>)> = config_files
+        .iter()
+        .map(|x| (x.name.clone(), x.settings.get("LAYOUT_NAME").cloned()))
+        .collect();
+    let problems = find_layout_name_problems(
+        name,
+        config.settings.get("LAYOUT_NAME").map(String::as_str),
+        &layout_names,
+    );
+    if !problems.is_empty() {
+        return Err(problems.join("\n"));
+    }
+    Ok(config)
 }
 
 pub fn launch_tasks(
     config_files: &Vec<Config>,
-    tasks: &mut Vec<JoinHandle<()>>,
-    environment: Environment,
+    tasks: &mut HashMap<String, ReaderTask>,
+    shared_state: &SharedState,
 ) {
-    let modifiers: Arc<Mutex<Vec<Event>>> = Arc::new(Mutex::new(Default::default()));
-    let modifier_was_activated: Arc<Mutex<bool>> = Arc::new(Mutex::new(true));
     let user_has_access = match Command::new("groups").output() {
         Ok(groups)
             if std::str::from_utf8(&groups.stdout.as_slice())
@@ -94,30 +437,11 @@ pub fn launch_tasks(
     for device in devices {
         let mut config_list: Vec<Config> = Vec::new();
         for mut config in config_files.clone() {
-            let split_config_name = config.name.split("::").collect::<Vec<&str>>();
-            let associated_device_name = split_config_name[0];
-            if associated_device_name == device.1.name().unwrap().replace("/", "") {
-                let (window_class, layout) = match split_config_name.len() {
-                    1 => (Client::Default, 0),
-                    2 => {
-                        if let Ok(layout) = split_config_name[1].parse::<u16>() {
-                            (Client::Default, layout)
-                        } else {
-                            (Client::Class(split_config_name[1].to_string()), 0)
-                        }
-                    }
-                    3 => {
-                        if let Ok(layout) = split_config_name[1].parse::<u16>() {
-                            (Client::Class(split_config_name[2].to_string()), layout)
-                        } else if let Ok(layout) = split_config_name[2].parse::<u16>() {
-                            (Client::Class(split_config_name[1].to_string()), layout)
-                        } else {
-                            println!("Warning: unable to parse layout number in {}, treating it as default.", config.name);
-                            (Client::Default, 0)
-                        }
-                    }
-                    _ => {
-                        println!("Warning: too many arguments in config file name {}, treating it as default.", config.name);
+            if is_associated(&config, config_files, &device.1) {
+                let (window_class, layout) = match parse_associations(&config.name) {
+                    Ok(associations) => associations,
+                    Err(error) => {
+                        println!("Warning: {}, treating it as default.", error);
                         (Client::Default, 0)
                     }
                 };
@@ -134,32 +458,52 @@ pub fn launch_tasks(
             config_list.push(Config::new_empty(device.1.name().unwrap().replace("/", "")));
         }
         let event_device = device.0.as_path().to_str().unwrap().to_string();
+        if tasks.contains_key(&event_device) {
+            continue;
+        }
         if config_list.len() != 0 {
             let stream = Arc::new(Mutex::new(get_event_stream(
                 Path::new(&event_device),
                 config_list.clone(),
             )));
-            let virt_dev = Arc::new(Mutex::new(VirtualDevices::new(device.1)));
-            let reader = EventReader::new(
+            let name = device.1.name().unwrap_or_default().to_string();
+            let motion_stream = get_motion_stream(&device.1, &config_list);
+            let virt_dev = Arc::new(Mutex::new(VirtualDevices::new(
+                device.1,
+                grabs_device(&config_list),
+            )));
+            let reader = match EventReader::new(
                 config_list.clone(),
                 virt_dev,
                 stream,
-                modifiers.clone(),
-                modifier_was_activated.clone(),
-                environment.clone(),
+                motion_stream,
+                shared_state.clone(),
+            ) {
+                Ok(reader) => Arc::new(reader),
+                Err(error) => {
+                    println!("Warning: not reading events from {:?}: {}\n", name, error);
+                    continue;
+                }
+            };
+            tasks.insert(
+                event_device,
+                ReaderTask {
+                    name,
+                    reader: reader.clone(),
+                    task: tokio::spawn(start_reader(reader)),
+                },
             );
-            tasks.push(tokio::spawn(start_reader(reader)));
             devices_found += 1
         }
     }
     if devices_found == 0 && !user_has_access {
         println!("No matching devices found.\nNote: make sure that your user has access to event devices.\n");
     } else if devices_found == 0 && user_has_access {
-        println!("No matching devices found.\nNote: double-check that your device and its associated config file have the same name, as reported by 'evtest'.\n");
+        println!("No matching devices found.\nNote: double-check that your device and its associated config file have the same name, as reported by 'evtest', or that the [device] table of the config file matches it.\n");
     }
 }
 
-pub async fn start_reader(reader: EventReader) {
+pub async fn start_reader(reader: Arc<EventReader>) {
     reader.start().await;
 }
 
@@ -265,6 +609,16 @@ fn copy_variables() {
 
 pub fn get_event_stream(path: &Path, config: Vec<Config>) -> EventStream {
     let mut device: Device = Device::open(path).expect("Couldn't open device path.");
+    if grabs_device(&config) {
+        device
+            .grab()
+            .expect("Unable to grab device. Is another instance of Makima running?")
+    }
+    let stream: EventStream = device.into_event_stream().unwrap();
+    return stream;
+}
+
+fn grabs_device(config: &[Config]) -> bool {
     match config
         .iter()
         .find(|&x| x.associations == Associations::default())
@@ -272,38 +626,186 @@ pub fn get_event_stream(path: &Path, config: Vec<Config>) -> EventStream {
         .settings
         .get("GRAB_DEVICE")
     {
-        Some(value) => {
-            if value == &true.to_string() {
-                device
-                    .grab()
-                    .expect("Unable to grab device. Is another instance of Makima running?")
-            }
+        Some(value) => value == &true.to_string(),
+        None => true,
+    }
+}
+
+fn is_motion_sensors_of(sensors: &Device, device: &Device) -> bool {
+    sensors.properties().contains(PropType::ACCELEROMETER)
+        && !device.properties().contains(PropType::ACCELEROMETER)
+        && sensors
+            .physical_path()
+            .is_some_and(|phys| !phys.is_empty() && Some(phys) == device.physical_path())
+}
+
+fn uses_gyro(config: &Config) -> bool {
+    config
+        .settings
+        .get("GYRO")
+        .is_some_and(|gyro| gyro == "cursor")
+}
+
+pub fn get_motion_stream(device: &Device, config: &[Config]) -> Option<EventStream> {
+    if !uses_gyro(
+        config
+            .iter()
+            .find(|&x| x.associations == Associations::default())
+            .unwrap(),
+    ) {
+        return None;
+    }
+    for (_, sensors) in evdev::enumerate() {
+        if is_motion_sensors_of(&sensors, device) {
+            return sensors.into_event_stream().ok();
         }
-        None => device
-            .grab()
-            .expect("Unable to grab device. Is another instance of Makima running?"),
     }
-    let stream: EventStream = device.into_event_stream().unwrap();
-    return stream;
+    println!(
+        "Warning: GYRO is enabled but no motion sensors were found for {:?}.\n",
+        device.name().unwrap_or_default()
+    );
+    None
 }
 
 pub fn is_mapped(udev_device: &tokio_udev::Device, config_files: &Vec<Config>) -> bool {
     match udev_device.devnode() {
         Some(devnode) => {
-            let evdev_devices: evdev::EnumerateDevices = evdev::enumerate();
-            for evdev_device in evdev_devices {
+            let evdev_devices: Vec<(std::path::PathBuf, Device)> = evdev::enumerate().collect();
+            for evdev_device in &evdev_devices {
                 for config in config_files {
-                    if config
-                        .name
-                        .contains(&evdev_device.1.name().unwrap().to_string().replace("/", ""))
+                    if is_associated(config, config_files, &evdev_device.1)
                         && devnode.to_path_buf() == evdev_device.0
                     {
                         return true;
                     }
                 }
             }
+            if let Some((_, sensors)) = evdev_devices.iter().find(|device| devnode == device.0) {
+                return evdev_devices.iter().any(|device| {
+                    is_motion_sensors_of(sensors, &device.1)
+                        && config_files.iter().any(|config| {
+                            uses_gyro(config) && is_associated(config, config_files, &device.1)
+                        })
+                });
+            }
         }
         _ => return false,
     }
     return false;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config_dir(name: &str) -> String {
+        let dir = env::temp_dir().join(format!("makima-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.to_str().unwrap().to_string()
+    }
+
+    fn write_config(dir: &str, filename: &str, content: &str) -> Vec<OsString> {
+        std::fs::write(format!("{}/{}", dir, filename), content).unwrap();
+        vec![OsString::from(filename)]
+    }
+
+    fn chain_only(config_files: &[Config], name: &str) -> Option<String> {
+        config_files
+            .iter()
+            .find(|config| config.name == name)
+            .and_then(|config| config.settings.get("CHAIN_ONLY").cloned())
+    }
+
+    #[test]
+    fn reload_loads_new_and_changed_files() {
+        let dir = config_dir("reload-changed");
+        let mut config_files: Vec<Config> = Vec::new();
+        let changed = write_config(&dir, "Keyboard.toml", "[settings]\nCHAIN_ONLY = \"true\"\n");
+        assert_eq!(
+            reload_config_files(&dir, &mut config_files, changed),
+            vec!["Keyboard"]
+        );
+        assert_eq!(
+            chain_only(&config_files, "Keyboard"),
+            Some("true".to_string())
+        );
+        let changed = write_config(
+            &dir,
+            "Keyboard.toml",
+            "[settings]\nCHAIN_ONLY = \"false\"\n",
+        );
+        reload_config_files(&dir, &mut config_files, changed);
+        assert_eq!(config_files.len(), 1);
+        assert_eq!(
+            chain_only(&config_files, "Keyboard"),
+            Some("false".to_string())
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reload_keeps_the_previous_version_of_invalid_files() {
+        let dir = config_dir("reload-invalid");
+        let mut config_files: Vec<Config> = Vec::new();
+        let changed = write_config(
+            &dir,
+            "Keyboard::1.toml",
+            "[settings]\nCHAIN_ONLY = \"true\"\n",
+        );
+        reload_config_files(&dir, &mut config_files, changed);
+        for content in [
+            "[settings\n",
+            "[settings]\nCHAIN_ONLY = \"maybe\"\n",
+            "[settings]\nLSTICK_SENSITIVITY = \"-1\"\n",
+            "[remap]\nKEY_A = [\"KEY_NOPE\"]\n",
+            "[remap]\nKEY_A = { turbo = [\"KEY_B\"], rate = 0 }\n",
+        ] {
+            let changed = write_config(&dir, "Keyboard::1.toml", content);
+            assert!(reload_config_files(&dir, &mut config_files, changed).is_empty());
+            assert_eq!(
+                chain_only(&config_files, "Keyboard::1"),
+                Some("true".to_string())
+            );
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn startup_ignores_the_files_reload_would_reject() {
+        let dir = config_dir("startup-invalid");
+        write_config(&dir, "Keyboard.toml", "[settings]\nCHAIN_ONLY = \"true\"\n");
+        write_config(&dir, "Mouse.toml", "[settings]\nCHAIN_ONLY = \"maybe\"\n");
+        write_config(&dir, "Pad.toml", "[remap]\nKEY_A = [\"KEY_NOPE\"]\n");
+        write_config(&dir, "notes.txt", "");
+        let config_files = load_config_files(&dir);
+        assert_eq!(config_files.len(), 1);
+        assert_eq!(
+            chain_only(&config_files, "Keyboard"),
+            Some("true".to_string())
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reload_drops_removed_files_and_ignores_other_files() {
+        let dir = config_dir("reload-removed");
+        let mut config_files: Vec<Config> = Vec::new();
+        let changed = write_config(&dir, "Keyboard.toml", "");
+        reload_config_files(&dir, &mut config_files, changed);
+        std::fs::remove_file(format!("{}/Keyboard.toml", dir)).unwrap();
+        assert_eq!(
+            reload_config_files(
+                &dir,
+                &mut config_files,
+                vec![OsString::from("Keyboard.toml")]
+            ),
+            vec!["Keyboard"]
+        );
+        assert!(config_files.is_empty());
+        let changed = write_config(&dir, ".Keyboard.toml.swp", "");
+        assert!(reload_config_files(&dir, &mut config_files, changed).is_empty());
+        assert!(config_files.is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
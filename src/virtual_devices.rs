@@ -1,16 +1,166 @@
+use crate::config::{Event, GamepadOutput, GamepadTarget, GAMEPAD_BUTTONS};
 use evdev::{
-    uinput::{VirtualDevice, VirtualDeviceBuilder},
-    Key,
+    uinput::{VirtualDevice, VirtualDeviceBuilder, VirtualEventStream},
+    AbsoluteAxisType, AttributeSet, EventType, FFEffect, FFEffectType, InputEvent, InputEventKind,
+    Key, LedType, UInputEventType,
 };
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::sync::{Arc, Weak};
+use tokio::io::unix::AsyncFd;
+use tokio::sync::{mpsc, Mutex};
+
+const MIRRORED_LEDS: [LedType; 3] = [LedType::LED_NUML, LedType::LED_CAPSL, LedType::LED_SCROLLL];
+
+nix::ioctl_write_int!(ui_set_evbit, b'U', 100);
+nix::ioctl_write_int!(ui_set_keybit, b'U', 101);
+nix::ioctl_write_int!(ui_set_ledbit, b'U', 105);
+nix::ioctl_write_ptr!(ui_dev_setup, b'U', 3, libc::uinput_setup);
+nix::ioctl_none!(ui_dev_create, b'U', 1);
 
 pub struct VirtualDevices {
-    pub keys: VirtualDevice,
+    pub keys: VirtualKeyboard,
     pub axis: VirtualDevice,
     pub abs: VirtualDevice,
+    physical: evdev::Device,
+    mirror_leds: bool,
+    layout_led: Option<(LedType, bool)>,
+}
+
+// evdev's VirtualDeviceBuilder can't advertise LEDs, so the virtual keyboard is created
+// through uinput directly. This lets the compositor set its lock key LEDs, which are
+// read back from the uinput file and mirrored to the grabbed physical keyboards.
+pub struct VirtualKeyboard {
+    file: File,
+}
+
+impl VirtualKeyboard {
+    fn new(name: &str, keys: &AttributeSet<Key>) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open("/dev/uinput")?;
+        let fd = file.as_raw_fd();
+        let mut setup: libc::uinput_setup = unsafe { std::mem::zeroed() };
+        setup.id = libc::input_id {
+            bustype: evdev::BusType::BUS_USB.0,
+            vendor: 0x1234,
+            product: 0x5678,
+            version: 0x111,
+        };
+        for (byte, name_byte) in setup.name.iter_mut().zip(name.bytes().take(79)) {
+            *byte = name_byte as libc::c_char;
+        }
+        unsafe {
+            ui_set_evbit(fd, EventType::KEY.0 as _)?;
+            for key in keys.iter() {
+                ui_set_keybit(fd, key.0 as _)?;
+            }
+            ui_set_evbit(fd, EventType::LED.0 as _)?;
+            for led in MIRRORED_LEDS {
+                ui_set_ledbit(fd, led.0 as _)?;
+            }
+            ui_dev_setup(fd, &setup)?;
+            ui_dev_create(fd)?;
+        }
+        Ok(Self { file })
+    }
+
+    pub fn emit(&mut self, events: &[InputEvent]) -> io::Result<()> {
+        let syn = InputEvent::new(EventType::SYNCHRONIZATION, 0, 0);
+        for event in events.iter().chain([&syn]) {
+            let raw: &libc::input_event = event.as_ref();
+            let bytes = unsafe {
+                std::slice::from_raw_parts(
+                    raw as *const libc::input_event as *const u8,
+                    std::mem::size_of::<libc::input_event>(),
+                )
+            };
+            self.file.write_all(bytes)?;
+        }
+        Ok(())
+    }
+
+    pub fn led_events(&self) -> io::Result<LedEvents> {
+        Ok(LedEvents {
+            // The cloned file is owned by the AsyncFd and never closed behind its back.
+            file: unsafe { AsyncFd::register(self.file.try_clone()?)? },
+        })
+    }
+}
+
+pub struct LedEvents {
+    file: AsyncFd<File>,
+}
+
+impl LedEvents {
+    pub async fn next(&mut self) -> io::Result<Vec<(LedType, bool)>> {
+        let size = std::mem::size_of::<libc::input_event>();
+        let mut buffer = vec![0u8; size * 16];
+        loop {
+            let mut guard = self.file.readable_mut().await?;
+            let length = match guard.try_io(|file| file.get_mut().read(&mut buffer)) {
+                Ok(result) => result?,
+                Err(_would_block) => continue,
+            };
+            if length == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            let leds: Vec<(LedType, bool)> = buffer[..length - length % size]
+                .chunks_exact(size)
+                .map(|chunk| unsafe {
+                    std::ptr::read_unaligned(chunk.as_ptr() as *const libc::input_event)
+                })
+                .filter(|event| event.type_ == EventType::LED.0)
+                .map(|event| (LedType(event.code), event.value != 0))
+                .collect();
+            if !leds.is_empty() {
+                return Ok(leds);
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct KeyboardLeds {
+    state: AttributeSet<LedType>,
+    keyboards: Vec<Weak<Mutex<VirtualDevices>>>,
+}
+
+impl KeyboardLeds {
+    pub async fn add_keyboard(&mut self, virt_dev: &Arc<Mutex<VirtualDevices>>) {
+        self.keyboards
+            .retain(|keyboard| keyboard.strong_count() > 0);
+        self.keyboards.push(Arc::downgrade(virt_dev));
+        virt_dev.lock().await.set_leds(&self.state);
+    }
+
+    pub async fn update(&mut self, leds: Vec<(LedType, bool)>) {
+        let previous = self.state.clone();
+        for (led, on) in leds {
+            if on {
+                self.state.insert(led);
+            } else {
+                self.state.remove(led);
+            }
+        }
+        if self.state.iter().eq(previous.iter()) {
+            return;
+        }
+        self.keyboards
+            .retain(|keyboard| keyboard.strong_count() > 0);
+        for keyboard in self.keyboards.iter().filter_map(Weak::upgrade) {
+            keyboard.lock().await.set_leds(&self.state);
+        }
+    }
 }
 
 impl VirtualDevices {
-    pub fn new(device: evdev::Device) -> Self {
+    pub fn new(device: evdev::Device, grabbed: bool) -> Self {
         let mut key_capabilities = evdev::AttributeSet::new();
         for i in 1..334 {
             key_capabilities.insert(Key(i));
@@ -56,10 +206,6 @@ impl VirtualDevices {
         let mut tab_msc = evdev::AttributeSet::new();
         tab_msc.insert(evdev::MiscType(0));
         let pointer_prop = device.properties();
-        let keys_builder = VirtualDeviceBuilder::new()
-            .expect("Unable to create virtual device through uinput. Take a look at the Troubleshooting section for more info.")
-            .name("Makima Virtual Keyboard/Mouse")
-            .with_keys(&key_capabilities).unwrap();
         let axis_builder = VirtualDeviceBuilder::new()
             .expect("Unable to create virtual device through uinput. Take a look at the Troubleshooting section for more info.")
             .name("Makima Virtual Pointer")
@@ -75,13 +221,274 @@ impl VirtualDevices {
         for abs_setup in tablet_abs_capabilities {
             abs_builder = abs_builder.with_absolute_axis(&abs_setup).unwrap();
         }
-        let virtual_device_keys = keys_builder.build().unwrap();
+        let virtual_device_keys = VirtualKeyboard::new("Makima Virtual Keyboard/Mouse", &key_capabilities)
+            .expect("Unable to create virtual device through uinput. Take a look at the Troubleshooting section for more info.");
         let virtual_device_axis = axis_builder.build().unwrap();
         let virtual_device_abs = abs_builder.build().unwrap();
+        let mirror_leds = grabbed
+            && device
+                .supported_leds()
+                .is_some_and(|leds| MIRRORED_LEDS.iter().any(|led| leds.contains(*led)));
         Self {
             keys: virtual_device_keys,
             axis: virtual_device_axis,
             abs: virtual_device_abs,
+            physical: device,
+            mirror_leds,
+            layout_led: None,
         }
     }
+
+    pub fn force_feedback(&mut self) -> Option<&mut evdev::Device> {
+        match self.physical.supported_ff() {
+            Some(effects) if effects.contains(FFEffectType::FF_RUMBLE) => Some(&mut self.physical),
+            _ => None,
+        }
+    }
+
+    pub fn emit_keys(&mut self, events: &[InputEvent]) -> io::Result<()> {
+        self.keys.emit(events)
+    }
+
+    pub fn mirrors_leds(&self) -> bool {
+        self.mirror_leds
+    }
+
+    fn set_leds(&mut self, state: &AttributeSet<LedType>) {
+        if !self.mirror_leds {
+            return;
+        }
+        for led in MIRRORED_LEDS {
+            if self.layout_led.map(|(layout_led, _)| layout_led) != Some(led) {
+                self.write_led(led, state.contains(led));
+            }
+        }
+    }
+
+    pub fn set_layout_led(&mut self, led: LedType, on: bool) {
+        if self.layout_led != Some((led, on)) {
+            self.layout_led = Some((led, on));
+            self.write_led(led, on);
+        }
+    }
+
+    fn write_led(&mut self, led: LedType, on: bool) {
+        if !self
+            .physical
+            .supported_leds()
+            .is_some_and(|leds| leds.contains(led))
+        {
+            return;
+        }
+        let event = InputEvent::new(EventType::LED, led.0, on as i32);
+        if let Err(error) = self.physical.send_events(&[event]) {
+            println!("Warning: unable to set keyboard LED: {}\n", error);
+        }
+    }
+}
+
+enum GamepadMessage {
+    Emit(Vec<InputEvent>),
+    ForceFeedbackTarget(Weak<Mutex<VirtualDevices>>),
+}
+
+pub struct VirtualGamepad {
+    sender: mpsc::UnboundedSender<GamepadMessage>,
+    pressed: Vec<(Event, GamepadOutput)>,
+    analog: HashMap<u16, f64>,
+}
+
+impl VirtualGamepad {
+    pub fn new() -> Self {
+        let mut button_capabilities = evdev::AttributeSet::new();
+        for button in GAMEPAD_BUTTONS {
+            button_capabilities.insert(button);
+        }
+        let mut builder = VirtualDeviceBuilder::new()
+            .expect("Unable to create virtual device through uinput. Take a look at the Troubleshooting section for more info.")
+            .name("Microsoft X-Box 360 pad")
+            .input_id(evdev::InputId::new(evdev::BusType::BUS_USB, 0x045e, 0x028e, 0x110))
+            .with_keys(&button_capabilities).unwrap()
+            .with_ff(&[FFEffectType::FF_RUMBLE].into_iter().collect::<evdev::AttributeSet<_>>()).unwrap()
+            .with_ff_effects_max(16);
+        for axis in [
+            AbsoluteAxisType::ABS_X,
+            AbsoluteAxisType::ABS_Y,
+            AbsoluteAxisType::ABS_RX,
+            AbsoluteAxisType::ABS_RY,
+            AbsoluteAxisType::ABS_Z,
+            AbsoluteAxisType::ABS_RZ,
+            AbsoluteAxisType::ABS_HAT0X,
+            AbsoluteAxisType::ABS_HAT0Y,
+        ] {
+            let (minimum, maximum) = get_gamepad_axis_range(axis);
+            let (fuzz, flat) = if maximum == 32767 { (16, 128) } else { (0, 0) };
+            let absinfo = evdev::AbsInfo::new(0, minimum, maximum, fuzz, flat, 0);
+            builder = builder
+                .with_absolute_axis(&evdev::UinputAbsSetup::new(axis, absinfo))
+                .unwrap();
+        }
+        let stream = builder.build().unwrap().into_event_stream().unwrap();
+        let (sender, receiver) = mpsc::unbounded_channel();
+        tokio::spawn(run_gamepad(stream, receiver));
+        Self {
+            sender,
+            pressed: Vec::new(),
+            analog: HashMap::new(),
+        }
+    }
+
+    pub fn add_force_feedback_target(&self, virt_dev: &Arc<Mutex<VirtualDevices>>) {
+        let _ = self
+            .sender
+            .send(GamepadMessage::ForceFeedbackTarget(Arc::downgrade(
+                virt_dev,
+            )));
+    }
+
+    pub fn press(&mut self, source: Event, output: GamepadOutput) {
+        self.pressed.retain(|(event, _)| *event != source);
+        self.pressed.push((source, output));
+        self.update(output.target);
+    }
+
+    pub fn release(&mut self, source: Event) -> bool {
+        let Some(index) = self.pressed.iter().position(|(event, _)| *event == source) else {
+            return false;
+        };
+        let (_, output) = self.pressed.remove(index);
+        self.update(output.target);
+        true
+    }
+
+    pub fn move_stick(&mut self, axes: (AbsoluteAxisType, AbsoluteAxisType), position: (f64, f64)) {
+        self.analog.insert(axes.0 .0, position.0);
+        self.analog.insert(axes.1 .0, position.1);
+        let virtual_events = [
+            self.get_event(GamepadTarget::Axis(axes.0, 1)),
+            self.get_event(GamepadTarget::Axis(axes.1, 1)),
+        ];
+        let _ = self
+            .sender
+            .send(GamepadMessage::Emit(virtual_events.to_vec()));
+    }
+
+    fn update(&mut self, target: GamepadTarget) {
+        let virtual_event = self.get_event(target);
+        let _ = self.sender.send(GamepadMessage::Emit(vec![virtual_event]));
+    }
+
+    fn get_event(&self, target: GamepadTarget) -> InputEvent {
+        match target {
+            GamepadTarget::Button(button) => {
+                let pressed = self
+                    .pressed
+                    .iter()
+                    .any(|(_, output)| output.target == target);
+                InputEvent::new_now(EventType::KEY, button.code(), pressed as i32)
+            }
+            GamepadTarget::Axis(axis, _) => {
+                let value: f64 = self
+                    .pressed
+                    .iter()
+                    .filter_map(|(_, output)| match output.target {
+                        GamepadTarget::Axis(pressed_axis, direction) if pressed_axis == axis => {
+                            Some(output.magnitude * direction as f64)
+                        }
+                        _ => None,
+                    })
+                    .sum::<f64>()
+                    + self.analog.get(&axis.0).unwrap_or(&0.0);
+                let maximum = get_gamepad_axis_range(axis).1 as f64;
+                InputEvent::new_now(
+                    EventType::ABSOLUTE,
+                    axis.0,
+                    (value.clamp(-1.0, 1.0) * maximum).round() as i32,
+                )
+            }
+            GamepadTarget::Trigger(axis) => {
+                let value: f64 = self
+                    .pressed
+                    .iter()
+                    .filter(|(_, output)| output.target == target)
+                    .map(|(_, output)| output.magnitude)
+                    .fold(0.0, f64::max);
+                let maximum = get_gamepad_axis_range(axis).1 as f64;
+                InputEvent::new_now(
+                    EventType::ABSOLUTE,
+                    axis.0,
+                    (value * maximum).round() as i32,
+                )
+            }
+        }
+    }
+}
+
+async fn run_gamepad(
+    mut stream: VirtualEventStream,
+    mut receiver: mpsc::UnboundedReceiver<GamepadMessage>,
+) {
+    let mut targets: Vec<Weak<Mutex<VirtualDevices>>> = Vec::new();
+    let mut effects: HashMap<i16, Vec<FFEffect>> = HashMap::new();
+    loop {
+        tokio::select! {
+            message = receiver.recv() => match message {
+                Some(GamepadMessage::Emit(virtual_events)) => {
+                    stream.device_mut().emit(&virtual_events).unwrap();
+                }
+                Some(GamepadMessage::ForceFeedbackTarget(target)) => targets.push(target),
+                None => break,
+            },
+            Ok(event) = stream.next_event() => {
+                targets.retain(|target| target.strong_count() > 0);
+                match event.kind() {
+                    InputEventKind::UInput(code) if code == UInputEventType::UI_FF_UPLOAD.0 => {
+                        let Some((effect_id, effect_data)) = stream
+                            .device_mut()
+                            .process_ff_upload(event)
+                            .ok()
+                            .map(|upload| (upload.effect_id(), upload.effect()))
+                        else {
+                            continue;
+                        };
+                        let mut uploaded: Vec<FFEffect> = Vec::new();
+                        for target in targets.iter().filter_map(Weak::upgrade) {
+                            if let Some(device) = target.lock().await.force_feedback() {
+                                match device.upload_ff_effect(effect_data) {
+                                    Ok(effect) => uploaded.push(effect),
+                                    Err(error) => println!(
+                                        "Warning: unable to forward a force feedback effect: {}\n",
+                                        error
+                                    ),
+                                }
+                            }
+                        }
+                        effects.insert(effect_id, uploaded);
+                    }
+                    InputEventKind::UInput(code) if code == UInputEventType::UI_FF_ERASE.0 => {
+                        if let Ok(erase) = stream.device_mut().process_ff_erase(event) {
+                            effects.remove(&(erase.effect_id() as i16));
+                        }
+                    }
+                    InputEventKind::ForceFeedback(effect_id) => {
+                        for effect in effects.get_mut(&(effect_id as i16)).into_iter().flatten() {
+                            let _ = match event.value() {
+                                0 => effect.stop(),
+                                count => effect.play(count),
+                            };
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+fn get_gamepad_axis_range(axis: AbsoluteAxisType) -> (i32, i32) {
+    match axis {
+        AbsoluteAxisType::ABS_Z | AbsoluteAxisType::ABS_RZ => (0, 255),
+        AbsoluteAxisType::ABS_HAT0X | AbsoluteAxisType::ABS_HAT0Y => (-1, 1),
+        _ => (-32768, 32767),
+    }
 }